@@ -1,8 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use x11rb::CURRENT_TIME;
 use x11rb::connection::Connection;
-use x11rb::errors::ReplyError;
+use x11rb::errors::{ConnectionError, ReplyError};
 use x11rb::errors::ReplyOrIdError;
 use x11rb::wrapper::ConnectionExt as _;
 use x11rb::xcb_ffi::XCBConnection;
@@ -17,6 +20,8 @@ use fork::{daemon, Fork};
 
 use tabbed_rs::*;
 use tabbed_rs::config::*;
+use tabbed_rs::ipc::{self, Incoming, Request, Response, TabEvent, TabInfo, TabRef};
+use tabbed_rs::keysym::{KeyboardMapping, XK_ESCAPE, is_modifier_keysym};
 use tabbed_rs::x11::*;
 
 #[derive(Parser, Debug)]
@@ -28,24 +33,52 @@ struct Cli {
     detach: bool,
     #[arg(long)]
     config: Option<PathBuf>,
+    /// Override a configuration key, e.g. `--set auto_attach=true`; can be repeated
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<Override>,
+    /// Check the configuration for errors and exit, with a non-zero status if any were found
+    #[arg(long)]
+    check_config: bool,
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    dump_config: bool,
+}
+
+/// How long to wait for the window manager to map a detached window before giving its original
+/// hints back anyway.
+const RESTORE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Everything the main loop waits on.
+enum Message {
+    X(Result<Event, ConnectionError>),
+    Ipc(Incoming),
 }
 
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let config = match read_config(&cli.config) {
+    if cli.check_config {
+        check(&cli);
+    }
+
+    let config = match read_config(&cli.config, &cli.overrides) {
         Ok(c) => c,
         Err(e) => {
             eprintln!("{}", e);
-            return Ok(());
+            std::process::exit(1);
         }
     };
 
+    if cli.dump_config {
+        print!("{}", toml::to_string(&config)?);
+        return Ok(());
+    }
+
     let (conn, screen_num) = XCBConnection::connect(None)?;
     // We leak the connection so it doesn't get dropped when detaching,
     // since it lives for the duration of the entire program anyway.
-    let conn = Box::leak(Box::new(conn));
+    let conn: &'static XCBConnection = Box::leak(Box::new(conn));
 
     let screen = &conn.setup().roots[screen_num];
     let atoms = Atoms::new(conn)?.reply()?;
@@ -76,21 +109,61 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    for keybind in &config.keybinds {
-        conn.grab_key(
-            true,
-            win_id,
-            keybind.mod_mask(),
-            keybind.key,
-            GrabMode::ASYNC,
-            GrabMode::ASYNC,
-        )?.check()?;
+    let mut tabbed = Tabbed::new(conn, &atoms, &cli, config, screen, win_id)?;
+    tabbed.grab_keys()?;
+    tabbed.grab_buttons()?;
+
+    // X events and IPC requests are read on their own threads so the main loop can wait on
+    // both, and wake up for timeouts.
+    let (sender, messages) = mpsc::channel();
+
+    let socket_path = ipc::socket_path(win_id);
+    match ipc::bind(&socket_path) {
+        Ok(listener) => {
+            ipc::serve(listener, sender.clone(), Message::Ipc);
+            conn.change_property8(
+                PropMode::REPLACE,
+                win_id,
+                atoms._TABBED_RS_SOCKET,
+                atoms.UTF8_STRING,
+                socket_path.to_string_lossy().as_bytes(),
+            )?;
+        },
+        Err(e) => eprintln!("cannot listen on {}: {}", socket_path.display(), e),
     }
 
-    let mut tabbed = Tabbed::new(&conn, &atoms, &cli, config, screen, win_id)?;
-
-    while tabbed.running {
-        let event = conn.wait_for_event()?;
+    thread::spawn(move || loop {
+        let event = conn.wait_for_event();
+        let failed = event.is_err();
+        if sender.send(Message::X(event)).is_err() || failed {
+            break;
+        }
+    });
+
+    // Windows that were just detached still need their hints back before we can go
+    while tabbed.running || !tabbed.restoring.is_empty() {
+        let timeout = tabbed.time_until_timeout().unwrap_or(Duration::MAX);
+        let event = match messages.recv_timeout(timeout) {
+            Ok(Message::X(event)) => event?,
+            Ok(Message::Ipc(Incoming::Request(request, reply))) => {
+                let _ = reply.send(tabbed.handle_request(request));
+                tabbed.publish_state()?;
+                tabbed.redraw_if_needed()?;
+                conn.sync()?;
+                continue;
+            },
+            Ok(Message::Ipc(Incoming::Subscribe(events))) => {
+                tabbed.subscribers.push(events);
+                continue;
+            },
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                tabbed.handle_timeout();
+                tabbed.publish_state()?;
+                tabbed.redraw_if_needed()?;
+                continue;
+            },
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
         println!("Event: {:?}\n", event);
 
         match event {
@@ -101,11 +174,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Event::MapNotify(e) => tabbed.handle_map_notify(e),
             Event::PropertyNotify(e) => tabbed.handle_property_notify(e),
             Event::ButtonPress(e) => tabbed.handle_button_press(e),
+            Event::MappingNotify(e) => tabbed.handle_mapping_notify(e)?,
             Event::ClientMessage(e) => {
                 let data = e.data.as_data32();
                 if e.format == 32 && e.window == win_id && data[0] == atoms.WM_DELETE_WINDOW {
                     tabbed.running = false;
                 }
+                // Anyone can send these, so a bad one mustn't take the tabs down with us
+                match rs_receive_command(conn, &atoms, &e) {
+                    Ok(Some((action, target))) => tabbed.handle_command(&action, target),
+                    Ok(None) => {},
+                    Err(e) => eprintln!("ignoring invalid command: {}", e),
+                }
             }
             Event::FocusIn(_) => tabbed.is_focused = true,
             Event::FocusOut(_) => tabbed.is_focused = false,
@@ -114,19 +194,40 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        if tabbed.need_redraw {
-            tabbed.drawbar()?;
-            tabbed.need_redraw = false;
-        }
+        tabbed.publish_state()?;
+        tabbed.redraw_if_needed()?;
         conn.sync()?;
     }
 
     tabbed.cleanup()?;
+    let _ = std::fs::remove_file(&socket_path);
 
     Ok(())
 }
 
 
+/// Run `--check-config`, exiting with a non-zero status if there are any problems.
+fn check(cli: &Cli) -> ! {
+    // Keys can only be resolved with a connection, but the rest is still worth checking
+    let mapping = match XCBConnection::connect(None) {
+        Ok((conn, _)) => KeyboardMapping::fetch(&conn).ok(),
+        Err(e) => {
+            eprintln!("not checking keys, cannot connect to the X server: {}", e);
+            None
+        },
+    };
+
+    let diagnostics = check_config(&cli.config, &cli.overrides, mapping.as_ref());
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+
+    if diagnostics.is_empty() {
+        println!("configuration ok");
+        std::process::exit(0);
+    }
+    std::process::exit(1);
+}
 
 
 struct Tabbed<'a> {
@@ -140,8 +241,19 @@ struct Tabbed<'a> {
     win_height: u16,
     children: Vec<Window>,
     child_names: HashMap<Window, String>,
+    child_classes: HashMap<Window, (String, String)>,
+    child_rules: HashMap<Window, WindowRules>,
+    urgent: HashSet<Window>,
+    subscribers: Vec<mpsc::Sender<TabEvent>>,
+    /// The tab order and active tab last written to the container's properties
+    published: Option<(Vec<Window>, Option<Window>)>,
+    keyboard: KeyboardMapping,
     focused: Option<usize>,
     is_focused: bool,
+    mode: Option<String>,
+    mode_deadline: Option<Instant>,
+    /// Detached windows whose original hints go back once they're mapped, or at the deadline
+    restoring: Vec<(OriginalHints, Instant)>,
     cairo_surface: cairo::XCBSurface,
     running: bool,
     need_redraw: bool,
@@ -157,6 +269,7 @@ impl<'a> Tabbed<'a> {
         win_id: Window,
     ) -> Result<Self, ReplyOrIdError> {
         let geometry = conn.get_geometry(win_id).unwrap().reply().unwrap();
+        let keyboard = KeyboardMapping::fetch(conn)?;
 
 
         let visualid = screen.root_visual;
@@ -186,8 +299,17 @@ impl<'a> Tabbed<'a> {
             win_height: geometry.height,
             children: vec![],
             child_names: HashMap::new(),
+            child_classes: HashMap::new(),
+            child_rules: HashMap::new(),
+            urgent: HashSet::new(),
+            subscribers: vec![],
+            published: None,
+            keyboard,
             focused: None,
             is_focused: true,
+            mode: None,
+            mode_deadline: None,
+            restoring: vec![],
             cairo_surface: surface,
             running: true,
             need_redraw: true,
@@ -195,15 +317,48 @@ impl<'a> Tabbed<'a> {
     }
 
 
-    fn swap_focused_relative(&mut self, offset: i32) {
+    /// Send an event to everyone subscribed on the IPC socket.
+    fn emit(&mut self, event: TabEvent) {
+        self.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Reorder the tabs, keeping the same window focused and the pinned tabs first.
+    fn reorder(&mut self, f: impl FnOnce(&mut Vec<Window>)) {
+        let old_children = self.children.clone();
+        let focused_window = self.focused.map(|i| self.children[i]);
+        f(&mut self.children);
+
+        let rules = &self.child_rules;
+        self.children.sort_by_key(|w| !rules.get(w).is_some_and(|r| r.pinned));
+
+        // The focused window stays the same, so this isn't a focus change.
+        self.focused = focused_window.and_then(|w| self.children.iter().position(|&c| c == w));
+        self.focus(self.focused);
+        self.need_redraw = true;
+
+        // Attaching a tab isn't a reorder, subscribers get an `attached` event for it instead.
+        if self.children.len() == old_children.len() && self.children != old_children {
+            self.emit(TabEvent::Reordered { windows: self.children.clone() });
+        }
+    }
+
+    fn swap_relative(&mut self, index: Option<usize>, offset: i32) {
         let len = self.children.len();
 
-        let target = self.focused
+        let target = index
             .and_then(|i| (i as i32 + offset).checked_rem_euclid(len as i32).map(|i| i as usize));
 
-        if let (Some(a), Some(b)) = (self.focused, target) {
-            self.children.swap(a, b);
-            self.focus(Some(b));
+        if let (Some(a), Some(b)) = (index, target) {
+            self.reorder(|children| children.swap(a, b));
+        }
+    }
+
+    fn move_tab(&mut self, from: usize, to: usize) {
+        if from < self.children.len() && to < self.children.len() {
+            self.reorder(|children| {
+                let wid = children.remove(from);
+                children.insert(to, wid);
+            });
         }
     }
 
@@ -230,10 +385,15 @@ impl<'a> Tabbed<'a> {
                 return;
             }
         }
-        if self.focused != focused {
+        let changed = self.focused != focused;
+        self.focused = focused;
+        if changed {
             self.need_redraw = true;
+            self.emit(TabEvent::Focused {
+                window: focused.map(|i| self.children[i]),
+                index: focused,
+            });
         }
-        self.focused = focused;
         if let Some(i) = self.focused {
             self.conn
                 .configure_window(
@@ -255,7 +415,7 @@ impl<'a> Tabbed<'a> {
         }
     }
 
-    fn drawbar(&self) -> Result<(), cairo::Error> {
+    fn bar_context(&self) -> Result<cairo::Context, cairo::Error> {
         let cr =
             cairo::Context::new(&self.cairo_surface)?;
 
@@ -265,24 +425,109 @@ impl<'a> Tabbed<'a> {
             cairo::FontWeight::Normal
         );
         cr.set_font_size(12.);
+        Ok(cr)
+    }
+
+    /// Width of the label in front of the tabs showing the active mode, if any.
+    fn mode_label_width(&self, cr: &cairo::Context) -> f64 {
+        match &self.mode {
+            Some(name) => cr.text_extents(name).map_or(0., |e| e.x_advance() + 10.),
+            None => 0.,
+        }
+    }
+
+    /// Index of the tab at `x` in the bar.
+    fn tab_at(&self, x: i16) -> Option<usize> {
+        let offset = self.bar_context().map_or(0., |cr| self.mode_label_width(&cr));
+        let tab_width = (self.win_width as f64 - offset) / self.children.len() as f64;
+
+        let index = ((x as f64 - offset) / tab_width).floor();
+        if index >= 0. && (index as usize) < self.children.len() {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Write the tab order and active tab to `_TABBED_RS_CLIENTS` and `_TABBED_RS_ACTIVE` on the
+    /// container if they changed, so any X client can inspect it.
+    fn publish_state(&mut self) -> Result<(), ReplyError> {
+        let state = (self.children.clone(), self.focused.map(|i| self.children[i]));
+        if self.published.as_ref() == Some(&state) {
+            return Ok(());
+        }
+
+        let (children, active) = &state;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            self.win_id,
+            self.atoms._TABBED_RS_CLIENTS,
+            AtomEnum::WINDOW,
+            children,
+        )?.check()?;
+        self.conn.change_property32(
+            PropMode::REPLACE,
+            self.win_id,
+            self.atoms._TABBED_RS_ACTIVE,
+            AtomEnum::WINDOW,
+            active.as_slice(),
+        )?.check()?;
+
+        self.published = Some(state);
+        Ok(())
+    }
 
-        let tab_width = self.win_width as f64 / self.children.len() as f64;
+    fn redraw_if_needed(&mut self) -> Result<(), cairo::Error> {
+        if self.need_redraw {
+            self.drawbar()?;
+            self.need_redraw = false;
+        }
+        Ok(())
+    }
+
+    fn drawbar(&self) -> Result<(), cairo::Error> {
+        let cr = self.bar_context()?;
+
+        let offset = self.mode_label_width(&cr);
+        let tab_width = (self.win_width as f64 - offset) / self.children.len() as f64;
         println!("{:?}", tab_width);
         if tab_width.is_infinite() {
             cr.set_source_rgb(0.5, 0.5, 0.5);
             cr.rectangle(0., 0., self.win_width as _, 20.);
             cr.fill()?;
         }
+
+        if let Some(name) = &self.mode {
+            cr.set_source_rgb(0.8, 0.8, 0.8);
+            cr.rectangle(0., 0., offset, 20.);
+            cr.fill()?;
+
+            cr.set_source_rgb(0., 0., 0.);
+            cr.move_to(5., 13.5);
+            cr.show_text(name)?;
+            cr.stroke()?;
+        }
         
         for i in 0..self.children.len() {
-            let name = self.child_names.get(&self.children[i]).map_or("", String::as_str);
-            let tab_x = i as f64 * tab_width;
-            let (r, g, b) = color_hash(&self.children[i]);
+            let wid = self.children[i];
+            let rules = self.child_rules.get(&wid);
+            let name = rules.and_then(|r| r.label.as_deref())
+                .or(self.child_names.get(&wid).map(String::as_str))
+                .unwrap_or("");
+            let tab_x = offset + i as f64 * tab_width;
+            let (r, g, b) = match rules.and_then(|r| r.color) {
+                Some(Color(r, g, b)) => (r, g, b),
+                None => color_hash(&wid),
+            };
 
             let (bg_bright, outline_height) =
                 if self.focused == Some(i) { (0.0, 14.) } else { (0.2, 0.) };
             
-            cr.set_source_rgb(bg_bright, bg_bright, bg_bright);
+            if self.urgent.contains(&wid) {
+                cr.set_source_rgb(0.6, 0.1, 0.1);
+            } else {
+                cr.set_source_rgb(bg_bright, bg_bright, bg_bright);
+            }
             cr.rectangle(tab_x, 0., tab_width, 20.);
             cr.fill()?;
 
@@ -293,7 +538,7 @@ impl<'a> Tabbed<'a> {
 
             cr.set_source_rgb(1., 1., 1.);
             cr.move_to(tab_x+5., 13.5);
-            cr.show_text(&name)?;
+            cr.show_text(name)?;
             cr.stroke()?;
             
         }
@@ -303,8 +548,40 @@ impl<'a> Tabbed<'a> {
     }
 
     fn manage(&mut self, wid: Window) {
-        self.children.push(wid);
-        self.focus(Some(self.children.len() - 1));
+        // A window attached again before it was placed keeps the hints it was detached with
+        self.finish_restore(wid);
+
+        let class = rs_get_wm_class(self.conn, wid).unwrap_or_default();
+        let name = rs_get_window_name(self.conn, self.atoms, wid).unwrap_or_default();
+        let rules = self.config.window_rules(&class.0, &class.1, &name);
+
+        if !rules.adopt {
+            // Short-lived windows like dialogs may already be gone
+            let _ = self.restore(wid);
+            return;
+        }
+        // Windows not attached by tabctrl haven't recorded their hints yet. Their position on
+        // the screen is already lost, so they're put back at the container's on detach.
+        let _ = rs_save_hints(self.conn, self.atoms, wid);
+
+        let index = rules.position.index(self.children.len(), self.focused);
+        let focus = rules.focus || self.focused.is_none();
+
+        self.child_classes.insert(wid, class);
+        self.child_names.insert(wid, name);
+        self.child_rules.insert(wid, rules);
+        if rs_get_urgency(self.conn, wid).unwrap_or(false) {
+            self.urgent.insert(wid);
+        }
+        self.reorder(|children| children.insert(index, wid));
+
+        if let Some(index) = self.children.iter().position(|&w| w == wid) {
+            self.emit(TabEvent::Attached { window: wid, index });
+        }
+
+        if focus {
+            self.focus(self.children.iter().position(|&w| w == wid));
+        }
 
         self.conn
             .change_window_attributes(
@@ -312,35 +589,105 @@ impl<'a> Tabbed<'a> {
                 &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
             ).unwrap();
 
-        self.check_name(wid);
-
         self.need_redraw = true;
     }
 
     fn unmanage(&mut self, wid: Window) {
-        let maybe_index = self.children.iter().position(|&w| w == wid);
-        if let Some(index) = maybe_index {
-            self.children.remove(index);
-        }
+        // Windows refused by a rule leave again without ever becoming tabs
+        let Some(index) = self.children.iter().position(|&w| w == wid) else { return };
+        self.children.remove(index);
+        self.emit(TabEvent::Detached { window: wid });
+        self.child_names.remove(&wid);
+        self.child_classes.remove(&wid);
+        self.child_rules.remove(&wid);
+        self.urgent.remove(&wid);
 
         if self.cli.close && self.children.is_empty() {
             self.running = false;
         }
 
-        if self.focused >= maybe_index {
+        if self.focused >= Some(index) {
             self.cycle_focus_down();
         }
 
         self.need_redraw = true;
     }
 
+    /// (Re-)grab all keybinds on the container using the current keyboard mapping.
+    fn grab_keys(&self) -> Result<(), ReplyError> {
+        self.conn.ungrab_key(Grab::ANY, self.win_id, ModMask::ANY)?.check()?;
+
+        for keybind in &self.config.keybinds {
+            let grabs = keybind.grabs(&self.keyboard).unwrap_or_else(|e| {
+                eprintln!("ignoring keybind {}: {}", keybind.key, e);
+                vec![]
+            });
+
+            for (mask, keycode) in grabs {
+                self.conn.grab_key(
+                    true,
+                    self.win_id,
+                    mask.into(),
+                    keycode,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )?.check()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// (Re-)grab the buttons of mousebinds on the client area.
+    ///
+    /// Clicks on the bar are always reported, so only `Region::Client` needs a grab.
+    fn grab_buttons(&self) -> Result<(), ReplyError> {
+        self.conn.ungrab_button(ButtonIndex::ANY, self.win_id, ModMask::ANY)?.check()?;
+
+        for mousebind in self.config.mousebinds.iter().filter(|m| m.region == Region::Client) {
+            let mask = match mousebind.mask(&self.keyboard) {
+                Ok(mask) => mask,
+                Err(e) => {
+                    eprintln!("ignoring mousebind for button {}: {}", mousebind.button, e);
+                    continue;
+                },
+            };
+
+            for mask in grab_masks(mask, &self.keyboard) {
+                self.conn.grab_button(
+                    false,
+                    self.win_id,
+                    EventMask::BUTTON_PRESS,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                    x11rb::NONE,
+                    x11rb::NONE,
+                    mousebind.button.into(),
+                    mask.into(),
+                )?.check()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_mapping_notify(&mut self, event: MappingNotifyEvent) -> Result<(), ReplyError> {
+        if event.request == Mapping::KEYBOARD || event.request == Mapping::MODIFIER {
+            self.keyboard = KeyboardMapping::fetch(self.conn)?;
+            self.grab_keys()?;
+            self.grab_buttons()?;
+        }
+        Ok(())
+    }
+
     fn handle_key_press(&mut self, event: KeyPressEvent) {
+        if self.mode.is_some() {
+            self.handle_mode_key_press(event);
+            return;
+        }
+
+        let state = u16::from(event.state);
         let maybe_action = self.config.keybinds
             .iter()
-            //.find(|Keybind { modifiers, key, .. }|
-            //      modifiers == &u32::from(event.state) && key == &event.detail)
-            .find(|keybind|
-                  keybind.key == event.detail && keybind.key_but_mask() == event.state)
+            .find(|keybind| keybind.matches(state, event.detail, &self.keyboard))
             .map(|Keybind { action, .. }| action.clone());
 
         if let Some(action) = maybe_action {
@@ -348,35 +695,265 @@ impl<'a> Tabbed<'a> {
         }
     }
 
+    fn handle_mode_key_press(&mut self, event: KeyPressEvent) {
+        let mode = match self.mode.as_ref().and_then(|name| self.config.modes.get(name)) {
+            Some(mode) => mode,
+            None => return self.exit_mode(),
+        };
+
+        let keysym = self.keyboard.keysym(event.detail);
+        if matches!(keysym, Some(sym) if is_modifier_keysym(sym)) {
+            return;
+        }
+
+        let state = u16::from(event.state);
+        let maybe_action = mode.keybinds
+            .iter()
+            .find(|keybind| keybind.matches(state, event.detail, &self.keyboard))
+            .map(|Keybind { action, .. }| action.clone());
+
+        match maybe_action {
+            Some(action) => {
+                if mode.sticky {
+                    self.mode_deadline = mode.timeout.map(|ms| Instant::now() + Duration::from_millis(ms));
+                } else {
+                    self.exit_mode();
+                }
+                self.do_action(&action);
+            },
+            None if mode.sticky && keysym != Some(XK_ESCAPE) => {},
+            None => self.exit_mode(),
+        }
+    }
+
+    fn enter_mode(&mut self, name: &str) {
+        let mode = match self.config.modes.get(name) {
+            Some(mode) => mode,
+            None => {
+                eprintln!("no mode named {}", name);
+                return;
+            },
+        };
+
+        if self.mode.is_none() {
+            let grab = self.conn
+                .grab_keyboard(false, self.win_id, CURRENT_TIME, GrabMode::ASYNC, GrabMode::ASYNC)
+                .map_err(ReplyError::from)
+                .and_then(|cookie| cookie.reply());
+            match grab {
+                Ok(reply) if reply.status == GrabStatus::SUCCESS => {},
+                Ok(reply) => {
+                    eprintln!("failed to grab the keyboard for mode {}: {:?}", name, reply.status);
+                    return;
+                },
+                Err(e) => {
+                    eprintln!("failed to grab the keyboard for mode {}: {}", name, e);
+                    return;
+                },
+            }
+        }
+
+        self.mode = Some(name.to_owned());
+        self.mode_deadline = mode.timeout.map(|ms| Instant::now() + Duration::from_millis(ms));
+        self.need_redraw = true;
+    }
+
+    fn exit_mode(&mut self) {
+        if self.mode.take().is_some() {
+            if let Err(e) = self.conn.ungrab_keyboard(CURRENT_TIME) {
+                eprintln!("failed to ungrab the keyboard: {}", e);
+            }
+            self.need_redraw = true;
+        }
+        self.mode_deadline = None;
+    }
+
+    fn time_until_timeout(&self) -> Option<Duration> {
+        self.mode_deadline.into_iter()
+            .chain(self.restoring.iter().map(|&(_, deadline)| deadline))
+            .min()
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    fn handle_timeout(&mut self) {
+        let now = Instant::now();
+        if matches!(self.mode_deadline, Some(deadline) if deadline <= now) {
+            self.exit_mode();
+        }
+
+        // The window manager may never map a window, e.g. on a desktop that isn't shown
+        let expired: Vec<_> = self.restoring.iter()
+            .filter(|&&(_, deadline)| deadline <= now)
+            .map(|(original, _)| original.window)
+            .collect();
+        for wid in expired {
+            self.finish_restore(wid);
+        }
+    }
+
     fn do_action(&mut self, action: &Action) {
+        self.do_action_on(action, self.focused);
+    }
+
+    /// Run an action, with `target` as the tab that single-tab actions apply to.
+    fn do_action_on(&mut self, action: &Action, target: Option<usize>) {
+        let last = self.children.len().saturating_sub(1);
+
         match action {
             Action::FocusUp => { self.cycle_focus_up(); },
             Action::FocusDown => { self.cycle_focus_down(); },
-            Action::ShiftUp => { self.swap_focused_relative(1); },
-            Action::ShiftDown => { self.swap_focused_relative(-1); },
+            Action::ShiftUp => { self.swap_relative(target, 1); },
+            Action::ShiftDown => { self.swap_relative(target, -1); },
             Action::Focus(index) => { self.focus(Some(*index)); },
-            Action::DetachFocused => { self.detach_focused().unwrap(); },
-            Action::DetachAll => { self.detach_all().unwrap(); },
+            Action::Select => if target.is_some() { self.focus(target); },
+            Action::MoveFirst => if let Some(i) = target { self.move_tab(i, 0); },
+            Action::MoveLast => if let Some(i) = target { self.move_tab(i, last); },
+            // The tab may have been destroyed before the action got here, which is no reason to
+            // take the others down
+            Action::Close => if let Some(i) = target {
+                if let Err(e) = self.close(i) {
+                    eprintln!("cannot close tab {}: {}", i, e);
+                }
+            },
+            Action::DetachFocused => if let Some(i) = target {
+                if let Err(e) = self.detach(i) {
+                    eprintln!("cannot detach tab {}: {}", i, e);
+                }
+            },
+            Action::DetachAll => if let Err(e) = self.detach_all() {
+                eprintln!("cannot detach all tabs: {}", e);
+            },
             Action::ToggleAutoAttach => {},
+            Action::EnterMode(name) => { self.enter_mode(name); },
+            Action::ExitMode => { self.exit_mode(); },
         }
     }
 
 
-    fn detach_focused(&mut self) -> Result<(), ReplyError> {
-        if let Some(index) = self.focused {
-            let active = self.children[index];
-            self.conn.reparent_window(active, self.screen.root, 0, 0)?.check()?;
+    /// Answer a request received on the IPC socket.
+    fn handle_request(&mut self, request: Request) -> Response {
+        match request {
+            Request::List => Response::tabs(self.tab_info()),
+            Request::Subscribe => Response::error("subscribe is handled by the connection"),
+            Request::Focus { tab } => self.tab_index(tab).map(|i| self.focus(Some(i))).into(),
+            Request::Move { tab, to } => self.tab_index(tab).and_then(|i| {
+                if to >= self.children.len() {
+                    return Err(format!("no tab at index {}", to));
+                }
+                self.move_tab(i, to);
+                Ok(())
+            }).into(),
+            Request::Swap { a, b } => self.tab_index(a).and_then(|a| {
+                let b = self.tab_index(b)?;
+                self.reorder(|children| children.swap(a, b));
+                Ok(())
+            }).into(),
+            Request::Detach { tab } => self.tab_index(tab)
+                .and_then(|i| self.detach(i).map_err(|e| e.to_string()))
+                .into(),
+            Request::Close { tab } => self.tab_index(tab)
+                .and_then(|i| self.close(i).map_err(|e| e.to_string()))
+                .into(),
+            Request::Action { action, tab } => {
+                let target = match tab.map(|tab| self.tab_index(tab)).transpose() {
+                    Ok(target) => target.or(self.focused),
+                    Err(e) => return Response::error(e),
+                };
+                self.do_action_on(&action, target);
+                Response::ok()
+            },
         }
-        Ok(())
     }
 
+    /// Run an action received as a `_TABBED_RS_COMMAND` client message.
+    fn handle_command(&mut self, action: &Action, target: Option<Window>) {
+        let target = match target {
+            Some(wid) => match self.children.iter().position(|&w| w == wid) {
+                Some(index) => Some(index),
+                None => {
+                    eprintln!("ignoring command for 0x{:x}, which is not a tab", wid);
+                    return;
+                },
+            },
+            None => self.focused,
+        };
+        self.do_action_on(action, target);
+    }
+
+    fn tab_index(&self, tab: TabRef) -> Result<usize, String> {
+        match tab {
+            TabRef::Index(i) if i < self.children.len() => Ok(i),
+            TabRef::Index(i) => Err(format!("no tab at index {}", i)),
+            TabRef::Window(wid) => self.children.iter().position(|&w| w == wid)
+                .ok_or_else(|| format!("window 0x{:x} is not a tab", wid)),
+        }
+    }
+
+    fn tab_info(&self) -> Vec<TabInfo> {
+        self.children.iter().enumerate().map(|(index, wid)| {
+            let (instance, class) = self.child_classes.get(wid).cloned().unwrap_or_default();
+            TabInfo {
+                index,
+                window: *wid,
+                title: self.child_names.get(wid).cloned().unwrap_or_default(),
+                instance,
+                class,
+                focused: self.focused == Some(index),
+                pinned: self.child_rules.get(wid).is_some_and(|r| r.pinned),
+                urgent: self.urgent.contains(wid),
+            }
+        }).collect()
+    }
+
+    fn detach(&mut self, index: usize) -> Result<(), ReplyError> {
+        let wid = self.children[index];
+        self.restore(wid)?;
+        rs_request_focus(self.conn, self.atoms, self.screen.root, wid)
+    }
+
+    /// Detach every tab, leaving the focused one focused.
     fn detach_all(&mut self) -> Result<(), ReplyError> {
-        for &wid in &self.children {
-            self.conn.reparent_window(wid, self.screen.root, 0, 0)?.check()?;
+        for wid in self.children.clone() {
+            self.restore(wid)?;
+        }
+        if let Some(i) = self.focused {
+            rs_request_focus(self.conn, self.atoms, self.screen.root, self.children[i])?;
         }
         Ok(())
     }
 
+    /// Put a tab back on the root window, with its original hints to follow once it's mapped.
+    fn restore(&mut self, wid: Window) -> Result<(), ReplyError> {
+        let original = rs_restore_window(self.conn, self.atoms, wid, self.win_id)?;
+        self.restoring.push((original, Instant::now() + RESTORE_TIMEOUT));
+        Ok(())
+    }
+
+    /// Put back the original hints of a window that was detached, if it's still waiting for them.
+    fn finish_restore(&mut self, wid: Window) {
+        let Some(i) = self.restoring.iter().position(|(original, _)| original.window == wid)
+            else { return };
+        let (original, _) = self.restoring.remove(i);
+
+        let no_events = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+        let restored = original.restore(self.conn)
+            .and_then(|_| self.conn.change_window_attributes(wid, &no_events).map(drop));
+        if let Err(e) = restored {
+            eprintln!("cannot restore the hints of 0x{:X}: {}", wid, e);
+        }
+    }
+
+    fn close(&mut self, index: usize) -> Result<(), ReplyError> {
+        let wid = self.children[index];
+        let protocols: Vec<u32> = rs_get_window_property32(self.conn, self.atoms.WM_PROTOCOLS, wid)?;
+
+        if protocols.contains(&self.atoms.WM_DELETE_WINDOW) {
+            rs_send_delete_window_event(self.conn, self.atoms, wid)
+        } else {
+            self.conn.kill_client(wid)?.check()
+        }
+    }
+
 
     fn handle_reparent_notify(&mut self, event: ReparentNotifyEvent) {
         if event.parent == self.win_id {
@@ -391,6 +968,7 @@ impl<'a> Tabbed<'a> {
     }
 
     fn handle_destroy_notify(&mut self, event: DestroyNotifyEvent) {
+        self.restoring.retain(|(original, _)| original.window != event.window);
         self.unmanage(event.window);
     }
 
@@ -415,6 +993,10 @@ impl<'a> Tabbed<'a> {
         if event.window == self.win_id && self.focused.is_some() {
             self.need_redraw = true;
         }
+        // The window manager has placed a detached window by now
+        if event.window != self.win_id {
+            self.finish_restore(event.window);
+        }
     }
 
     fn handle_property_notify(&mut self, event: PropertyNotifyEvent) {
@@ -422,29 +1004,77 @@ impl<'a> Tabbed<'a> {
             let reply = self.conn.get_atom_name(event.atom).unwrap().reply().unwrap();
             println!("{:?}", std::str::from_utf8(&reply.name));
         }
-        if self.children.contains(&event.window) && event.atom == AtomEnum::WM_NAME.into() {
+        let is_name = event.atom == u32::from(AtomEnum::WM_NAME) || event.atom == self.atoms._NET_WM_NAME;
+        if self.children.contains(&event.window) && is_name {
             self.check_name(event.window);
         }
+        if self.children.contains(&event.window) && event.atom == u32::from(AtomEnum::WM_HINTS) {
+            self.check_urgency(event.window);
+        }
     }
 
     fn handle_button_press(&mut self, event: ButtonPressEvent) {
-        let ButtonPressEvent { event_x, event_y, .. } = event;
+        let ButtonPressEvent { event_x, event_y, detail, state, .. } = event;
 
-        if self.children.is_empty() || event_y > 20 {
-            return;
-        }
+        let (region, target) = if event_y < 20 {
+            match self.tab_at(event_x) {
+                Some(index) => (Region::Tab, Some(index)),
+                None => (Region::Bar, None),
+            }
+        } else {
+            (Region::Client, self.focused)
+        };
+
+        let state = u16::from(state);
+        let maybe_action = self.config.mousebinds
+            .iter()
+            .find(|mousebind| mousebind.matches(detail, state, region, &self.keyboard))
+            .map(|Mousebind { action, .. }| action.clone());
 
-        let tab_width = self.win_width as f64 / self.children.len() as f64;
-        let index = (event_x as f64 / tab_width).floor() as usize;
-        self.focus(Some(index));
+        if let Some(action) = maybe_action {
+            self.do_action_on(&action, target);
+        }
     }
 
     fn check_name(&mut self, wid: Window) {
         let new_name = rs_get_window_name(self.conn, self.atoms, wid).unwrap_or_default();
         let old_name = self.child_names.insert(wid, new_name.clone());
 
-        if old_name != Some(new_name) {
+        if old_name.as_ref() != Some(&new_name) {
+            self.update_rules(wid);
             self.need_redraw = true;
+            self.emit(TabEvent::TitleChanged { window: wid, title: new_name });
+        }
+    }
+
+    fn check_urgency(&mut self, wid: Window) {
+        let urgent = rs_get_urgency(self.conn, wid).unwrap_or(false);
+        let changed = if urgent { self.urgent.insert(wid) } else { self.urgent.remove(&wid) };
+
+        if changed {
+            self.need_redraw = true;
+            self.emit(TabEvent::UrgencyChanged { window: wid, urgent });
+        }
+    }
+
+    /// Re-evaluate the rules for a window, e.g. after its title changed.
+    ///
+    /// Only the label, color and pinning are updated, adoption, position and focus are decided
+    /// once in [`Tabbed::manage`].
+    fn update_rules(&mut self, wid: Window) {
+        let (instance, class) = self.child_classes.get(&wid).cloned().unwrap_or_default();
+        let name = self.child_names.get(&wid).cloned().unwrap_or_default();
+        let new_rules = self.config.window_rules(&instance, &class, &name);
+
+        if let Some(rules) = self.child_rules.get_mut(&wid) {
+            let pinned_changed = rules.pinned != new_rules.pinned;
+            rules.color = new_rules.color;
+            rules.label = new_rules.label;
+            rules.pinned = new_rules.pinned;
+
+            if pinned_changed {
+                self.reorder(|_| {});
+            }
         }
     }
 
@@ -0,0 +1,167 @@
+//! `tabctrl daemon`, which tabs new windows together according to rules.
+//!
+//! Each rule matches windows by WM_CLASS, title and workspace and names the group they belong to.
+//! The first window of a group is left alone, and later ones are attached to it like `transfer`
+//! does, creating a container on the second window.
+
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::*;
+
+use serde::Deserialize;
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use tabbed_rs::config::{user_config_dir, Pattern};
+use tabbed_rs::x11::*;
+
+use crate::error::{Error, Result};
+use crate::{is_tabbed, transfer, Ctl, NewWindows};
+
+
+const CONFIG_FILE_NAME: &str = "tabctrl.toml";
+
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DaemonConfig {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<GroupRule>,
+}
+
+/// Puts the windows whose WM_CLASS instance and class, title and workspace name match into a
+/// group. Unset patterns match anything.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GroupRule {
+    #[serde(default)]
+    pub class: Option<Pattern>,
+    #[serde(default)]
+    pub instance: Option<Pattern>,
+    #[serde(default)]
+    pub title: Option<Pattern>,
+    /// Matched against the name from `_NET_DESKTOP_NAMES`, or the number if there is none
+    #[serde(default)]
+    pub workspace: Option<Pattern>,
+
+    pub group: String,
+    /// Keep a separate container for each workspace instead of a single one
+    #[serde(default)]
+    pub per_workspace: bool,
+}
+
+impl DaemonConfig {
+    /// Read the rules from `path`, or else `$XDG_CONFIG_HOME/tabctrl.toml`.
+    pub fn load(path: Option<PathBuf>) -> Result<Self> {
+        let path = path
+            .or_else(|| user_config_dir().map(|dir| dir.join(CONFIG_FILE_NAME)))
+            .ok_or_else(|| Error::Config("cannot find the config directory".to_owned()))?;
+
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&text).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+}
+
+
+/// A group, by name and by workspace for rules with `per_workspace`.
+type GroupKey = (String, Option<u32>);
+
+struct Daemon<'a> {
+    ctl: &'a Ctl,
+    rules: &'a [GroupRule],
+    /// The container of each group, or its only window before there is a second one
+    groups: HashMap<GroupKey, Window>,
+}
+
+/// Watch for new windows until the connection to the X server is lost.
+pub fn run(ctl: &Ctl, config: &DaemonConfig) -> Result<()> {
+    let mut new_windows = NewWindows::watch(ctl)?;
+    let mut daemon = Daemon {
+        ctl,
+        rules: &config.rules,
+        groups: HashMap::new(),
+    };
+
+    while let Some(wid) = new_windows.next(ctl, None, None)? {
+        // A window that can't be placed shouldn't stop the others from being placed
+        if let Err(e) = daemon.place(wid) {
+            eprintln!("tabctrl: 0x{:X}: {}", wid, e);
+        }
+    }
+    Ok(())
+}
+
+impl Daemon<'_> {
+    /// Attach a new window to its group, if the first matching rule gives it one.
+    fn place(&mut self, wid: Window) -> Result<()> {
+        let conn = &self.ctl.conn;
+
+        // Someone else got to it first, e.g. `embed` or a container with auto_attach
+        if is_tabbed(conn, conn.query_tree(wid)?.reply()?.parent)? {
+            return Ok(());
+        }
+
+        let (instance, class) = rs_get_wm_class(conn, wid)?;
+        let title = rs_get_window_name(conn, &self.ctl.atoms, wid)?;
+        let workspace = self.workspace(wid)?;
+
+        let rule = self.rules.iter().find(|rule| {
+            Pattern::matches(&rule.instance, &instance)
+                && Pattern::matches(&rule.class, &class)
+                && Pattern::matches(&rule.title, &title)
+                && (rule.workspace.is_none() || workspace.as_ref()
+                    .is_some_and(|(_, name)| Pattern::matches(&rule.workspace, name)))
+        });
+        let Some(rule) = rule else { return Ok(()) };
+
+        let desktop = workspace.map(|(desktop, _)| desktop).filter(|_| rule.per_workspace);
+        let key = (rule.group.clone(), desktop);
+
+        let container = match self.target(&key)? {
+            Some(target) => transfer(self.ctl, wid, target)?,
+            None => wid,
+        };
+        self.groups.insert(key, container);
+        Ok(())
+    }
+
+    /// Where to attach the next window of a group, if the group still has a window.
+    fn target(&mut self, key: &GroupKey) -> Result<Option<Window>> {
+        let Some(&wid) = self.groups.get(key) else { return Ok(None) };
+
+        let parent = match self.ctl.conn.query_tree(wid)?.reply() {
+            Ok(tree) => tree.parent,
+            Err(ReplyError::X11Error(_)) => {
+                self.groups.remove(key);
+                return Ok(None);
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        // The first window may have been tabbed by hand since
+        if is_tabbed(&self.ctl.conn, parent)? {
+            Ok(Some(parent))
+        } else {
+            Ok(Some(wid))
+        }
+    }
+
+    /// The number and name of the workspace `wid` is on, if the window manager says.
+    fn workspace(&self, wid: Window) -> Result<Option<(u32, String)>> {
+        let Ctl { conn, atoms, root, .. } = self.ctl;
+
+        let Some(&desktop) = rs_get_window_property32(conn, atoms._NET_WM_DESKTOP, wid)?.first()
+            else { return Ok(None) };
+
+        let names = rs_get_window_property8(conn, atoms._NET_DESKTOP_NAMES, *root)?;
+        let name = names.split(|&b| b == 0)
+            .nth(desktop as usize)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .unwrap_or_else(|| desktop.to_string());
+
+        Ok(Some((desktop, name)))
+    }
+
+}
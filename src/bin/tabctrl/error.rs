@@ -0,0 +1,107 @@
+//! Everything that can make tabctrl fail, each with its own exit code so scripts can tell them
+//! apart.
+
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::xproto::Window;
+use x11rb::protocol::ErrorKind;
+use x11rb::x11_utils::X11Error;
+
+use std::fmt;
+
+
+#[derive(Debug)]
+pub enum Error {
+    /// The X server couldn't be reached or failed a request
+    X11(String),
+    /// An argument that clap can't check, like a tab index out of range
+    Usage(String),
+    /// A window doesn't exist or a selector didn't match anything
+    BadWindow(String),
+    NotAContainer(Window),
+    /// The container program couldn't be started or didn't print its window id
+    Spawn(String),
+    /// The window manager couldn't be driven, e.g. `bspc` failed
+    Wm(String),
+    /// Something didn't happen in time, like a new window showing up for `embed`
+    Timeout(String),
+    /// A tabbed-rs instance couldn't be reached or refused a request
+    Ipc(String),
+    /// The daemon's rules couldn't be read
+    Config(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// The exit code for this error; usage errors share 2 with clap's.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::X11(_) => 1,
+            Error::Usage(_) => 2,
+            Error::BadWindow(_) => 3,
+            Error::NotAContainer(_) => 4,
+            Error::Spawn(_) => 5,
+            Error::Wm(_) => 6,
+            Error::Timeout(_) => 7,
+            Error::Ipc(_) => 8,
+            Error::Config(_) => 9,
+        }
+    }
+
+    fn from_x11(e: X11Error) -> Self {
+        match e.error_kind {
+            ErrorKind::Window | ErrorKind::Drawable => {
+                Error::BadWindow(format!("no window 0x{:X}", e.bad_value))
+            },
+            _ => Error::X11(format!("{:?}", e)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::X11(e) => write!(f, "X11 error: {}", e),
+            Error::Usage(e) => write!(f, "{}", e),
+            Error::BadWindow(e) => write!(f, "{}", e),
+            Error::NotAContainer(wid) => write!(f, "0x{:X} is not a container", wid),
+            Error::Spawn(e) => write!(f, "cannot start container: {}", e),
+            Error::Wm(e) => write!(f, "window manager: {}", e),
+            Error::Timeout(e) => write!(f, "timed out {}", e),
+            Error::Ipc(e) => write!(f, "{}", e),
+            Error::Config(e) => write!(f, "invalid rules: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ReplyError> for Error {
+    fn from(e: ReplyError) -> Self {
+        match e {
+            ReplyError::X11Error(e) => Error::from_x11(e),
+            e => Error::X11(e.to_string()),
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for Error {
+    fn from(e: ReplyOrIdError) -> Self {
+        match e {
+            ReplyOrIdError::X11Error(e) => Error::from_x11(e),
+            e => Error::X11(e.to_string()),
+        }
+    }
+}
+
+impl From<ConnectionError> for Error {
+    fn from(e: ConnectionError) -> Self {
+        Error::X11(e.to_string())
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(e: ConnectError) -> Self {
+        Error::X11(e.to_string())
+    }
+}
@@ -0,0 +1,88 @@
+//! Descriptions of containers and their tabs, for `query` and `list`.
+
+use x11rb::protocol::xproto::*;
+
+use serde::Serialize;
+
+use tabbed_rs::x11::*;
+
+use crate::error::Result;
+use crate::{active, clients, Container, Ctl};
+
+
+#[derive(Debug, Serialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TabInfo {
+    pub window: Window,
+    pub title: String,
+    pub instance: String,
+    pub class: String,
+    pub pid: Option<u32>,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ContainerInfo {
+    pub id: Window,
+    /// `tabbed-rs` or `tabbed`, or `None` if the window isn't a container
+    pub container: Option<String>,
+    pub geometry: Geometry,
+    pub tabs: Vec<TabInfo>,
+}
+
+impl ContainerInfo {
+    pub fn fetch(ctl: &Ctl, wid: Window) -> Result<Self> {
+        let container = Container::of(&ctl.conn, wid)?;
+
+        let (active, tabs) = match container {
+            Some(_) => (active(ctl, wid)?, clients(ctl, wid)?),
+            None => (None, vec![]),
+        };
+        let tabs = tabs.into_iter()
+            .map(|tab| TabInfo::fetch(ctl, tab, active == Some(tab)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(ContainerInfo {
+            id: wid,
+            container: container.map(|container| container.class().to_owned()),
+            geometry: geometry(ctl, wid)?,
+            tabs,
+        })
+    }
+}
+
+impl TabInfo {
+    fn fetch(ctl: &Ctl, wid: Window, active: bool) -> Result<Self> {
+        let (instance, class) = rs_get_wm_class(&ctl.conn, wid)?;
+        let pid = rs_get_window_property32(&ctl.conn, ctl.atoms._NET_WM_PID, wid)?;
+
+        Ok(TabInfo {
+            window: wid,
+            title: rs_get_window_name(&ctl.conn, &ctl.atoms, wid)?,
+            instance,
+            class,
+            pid: pid.first().copied(),
+            active,
+        })
+    }
+}
+
+/// The position of a window relative to the root window, and its size.
+fn geometry(ctl: &Ctl, wid: Window) -> Result<Geometry> {
+    let geometry = ctl.conn.get_geometry(wid)?.reply()?;
+    let origin = ctl.conn.translate_coordinates(wid, ctl.root, 0, 0)?.reply()?;
+
+    Ok(Geometry {
+        x: origin.dst_x.into(),
+        y: origin.dst_y.into(),
+        width: geometry.width.into(),
+        height: geometry.height.into(),
+    })
+}
@@ -0,0 +1,864 @@
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::*;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+
+use std::collections::{HashSet, VecDeque};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, ExitCode, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+
+use nonempty::{NonEmpty, nonempty};
+
+
+use tabbed_rs::*;
+use tabbed_rs::config::Action;
+use tabbed_rs::ipc::{self, Request, TabRef};
+use tabbed_rs::x11::*;
+
+
+mod daemon;
+mod error;
+mod info;
+mod selector;
+mod wm;
+use error::{Error, Result};
+use info::ContainerInfo;
+use selector::{Selector, TabArg};
+use wm::{Direction, WmBackend, WmChoice};
+
+
+/// Utility functions to manipulate a tabbed window.
+/// All input window ids can be in decimal, hex with the prefix "0x", or a selector: `focused` for
+/// the active window, `pointer` for the window under the pointer, `class:NAME` and `title:REGEX`
+/// for the first window with that WM_CLASS or title, and `tabbed:N` or `tabbed:CONTAINER:N` for
+/// the Nth tab of the focused or given container.
+///
+/// Exit codes: 1 for X11 errors, 2 for usage errors, 3 for windows that don't exist or selectors
+/// that match nothing, 4 for windows that aren't containers, 5 if a container couldn't be
+/// started, 6 for window manager errors, 7 for timeouts, 8 if tabbed-rs couldn't be reached and 9
+/// for invalid daemon rules.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    /// Window manager to drive for focusing and finding windows
+    #[arg(long, value_enum, default_value_t = WmChoice::Auto, global = true)]
+    wm: WmChoice,
+
+    /// Program to spawn when a new container is needed
+    #[arg(long, value_enum, default_value_t = Container::TabbedRs, global = true)]
+    container: Container,
+
+    /// Seconds to wait for a new container to print its window id
+    #[arg(long, default_value_t = 10, global = true)]
+    spawn_timeout: u64,
+}
+
+/// The programs whose windows tabctrl treats as containers.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    TabbedRs,
+    /// suckless tabbed
+    Tabbed,
+}
+
+impl Container {
+    fn class(self) -> &'static str {
+        match self {
+            Container::TabbedRs => TABBED_WINDOW_CLASS,
+            Container::Tabbed => "tabbed",
+        }
+    }
+
+    /// Which kind of container `wid` is, if any, going by its WM_CLASS.
+    fn of(conn: &RustConnection, wid: Window) -> Result<Option<Container>, ReplyError> {
+        let (instance, class) = rs_get_wm_class(conn, wid)?;
+
+        // Older tabbed-rs versions only set the instance
+        Ok([Container::TabbedRs, Container::Tabbed]
+            .into_iter()
+            .find(|container| class == container.class()
+                || (class.is_empty() && instance == container.class())))
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Reparent a set of windows to a tabbed instance, creating one if necessary
+    Create {
+        // Window IDs to combine into a tabbed instance
+        #[arg(num_args=1..)]
+        wids: Vec<Selector>,
+    },
+    /// Attach window <WID0> to tabbed <WID1>.
+    ///
+    /// If <WID0> is tabbed, use the active window instead.
+    /// If <WID1> is not tabbed, call `create <WID1>` first.
+    Transfer {
+        wid0: Selector,
+        wid1: Selector,
+    },
+    /// Move all tabs of <A> into <B>, after the tabs of <B> and keeping its active tab
+    ///
+    /// Either can also be a plain window, which is treated as a container with a single tab.
+    Merge {
+        a: Selector,
+        b: Selector,
+    },
+    /// Move the tabs of a container from <AT> on into a new container next to it
+    ///
+    /// Only tiling window managers place the new container next to the old one, others put it
+    /// wherever they put new windows.
+    Split {
+        container: Selector,
+
+        /// First tab to move, by default the active one, or the second if the first is active
+        #[arg(long)]
+        at: Option<usize>,
+    },
+    /// Detach from a tabbed container; by default, detaches active window only
+    Detach {
+        /// Window to detach from, expected to be a tabbed instance, no-op otherwise
+        wid: Selector,
+
+        /// Detach all children of the window instead of only active; deletes the tabbed instance
+        #[arg(short,long)]
+        all: bool,
+    },
+    /// Describe a window, and its tabs if it's a container
+    Query {
+        wid: Selector,
+
+        /// Print JSON with the geometry and the tabs' titles, classes and pids
+        #[arg(long)]
+        json: bool,
+    },
+    /// List every container on the display with its tabs
+    List {
+        /// Print JSON, as an array of what `query --json` prints
+        #[arg(long)]
+        json: bool,
+
+        /// Also list suckless tabbed containers
+        #[arg(long)]
+        include_tabbed: bool,
+    },
+    /// Focus a tab of a tabbed-rs container
+    Focus {
+        container: Selector,
+        tab: TabArg,
+    },
+    /// Move a tab of a tabbed-rs container to another position
+    Move {
+        container: Selector,
+        from: TabArg,
+        to: usize,
+    },
+    /// Swap two tabs of a tabbed-rs container
+    Swap {
+        container: Selector,
+        a: TabArg,
+        b: TabArg,
+    },
+    /// Ask a tab's window to close
+    Close {
+        wid: Selector,
+    },
+    /// Focus the next tab of a tabbed-rs container
+    Next {
+        container: Selector,
+    },
+    /// Focus the previous tab of a tabbed-rs container
+    Prev {
+        container: Selector,
+    },
+    /// Run a tabbed-rs action in a container, e.g. `FocusUp` or `{"Focus":2}`
+    Action {
+        /// The container
+        wid: Selector,
+        /// The action, as its name or as JSON
+        #[arg(value_parser=parse_action)]
+        action: Action,
+        /// Tab to apply the action to instead of the active one
+        #[arg(short, long)]
+        tab: Option<Selector>,
+    },
+    /// Tab the focused window with its neighbor in <DIRECTION>, creating a container if needed
+    ///
+    /// If the focused window is a container, its active tab is moved instead.
+    Group {
+        #[arg(value_enum)]
+        direction: Direction,
+    },
+    /// Detach the active tab of a container, the focused one by default
+    Ungroup {
+        #[arg(default_value = "focused")]
+        container: Selector,
+    },
+    /// Print the window next to <WID> in <DIRECTION>, according to the window manager
+    Neighbor {
+        wid: Selector,
+        #[arg(value_enum)]
+        direction: Direction,
+    },
+    /// Embed the next opened program with the target window
+    Embed {
+        /// Target window to autoattach to once
+        wid: Selector,
+
+        /// Only embed a window with this WM_CLASS class or instance
+        #[arg(short, long)]
+        class: Option<String>,
+
+        /// Seconds to wait for a window before giving up, 0 to wait forever
+        #[arg(short, long, default_value_t = 30)]
+        timeout: u64,
+    },
+    /// Keep running and tab new windows together according to rules
+    ///
+    /// The rules are read from `$XDG_CONFIG_HOME/tabctrl.toml` (`$HOME/.config/tabctrl.toml` by
+    /// default).
+    Daemon {
+        /// Read the rules from this file instead
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+    },
+}
+
+
+
+
+/// The X connection and what tabctrl knows about the display, shared by the subcommands.
+struct Ctl {
+    conn: RustConnection,
+    atoms: Atoms,
+    root: Window,
+    wm: Box<dyn WmBackend>,
+    /// Program to spawn for new containers
+    container: Container,
+    spawn_timeout: Duration,
+}
+
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("tabctrl: {}", e);
+            ExitCode::from(e.exit_code())
+        },
+    }
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let atoms = Atoms::new(&conn)?.reply()?;
+    let wm = wm::backend(&conn, &atoms, root, cli.wm)?;
+    let ctl = Ctl {
+        conn,
+        atoms,
+        root,
+        wm,
+        container: cli.container,
+        spawn_timeout: Duration::from_secs(cli.spawn_timeout),
+    };
+
+    match cli.command {
+        Commands::Create { wids } => {
+            let wids = wids.iter().map(|wid| wid.resolve(&ctl)).collect::<Result<_, _>>()?;
+            let wids = NonEmpty::from_vec(wids)
+                .ok_or_else(|| Error::BadWindow("no windows to combine".to_owned()))?;
+            create(&ctl, wids)?;
+        },
+        Commands::Transfer { wid0, wid1 } => {
+            transfer(&ctl, wid0.resolve(&ctl)?, wid1.resolve(&ctl)?)?;
+        },
+        Commands::Merge { a, b } => {
+            merge(&ctl, a.resolve(&ctl)?, b.resolve(&ctl)?)?;
+        },
+        Commands::Split { container, at } => {
+            let new = split(&ctl, container.resolve(&ctl)?, at)?;
+            println!("0x{:X}", new);
+        },
+        Commands::Detach { wid, all: true } => {
+            let container = wid.resolve(&ctl)?;
+            let active = active(&ctl, container)?;
+            reparent_all(&ctl, container, root)?;
+            focus_detached(&ctl, active)?;
+        },
+        Commands::Detach { wid, all: false } => {
+            let tab = reparent_current(&ctl, wid.resolve(&ctl)?, root)?;
+            focus_detached(&ctl, tab)?;
+        },
+        Commands::Query { wid, json: false } => {
+            query(&ctl, wid.resolve(&ctl)?)?;
+        },
+        Commands::Query { wid, json: true } => {
+            let info = ContainerInfo::fetch(&ctl, wid.resolve(&ctl)?)?;
+            println!("{}", serde_json::to_string(&info).expect("container info is serializable"));
+        },
+        Commands::List { json, include_tabbed } => {
+            list(&ctl, json, include_tabbed)?;
+        },
+        Commands::Focus { container, tab } => {
+            let request = Request::Focus { tab: tab.resolve(&ctl)? };
+            send_request(&ctl, container.resolve(&ctl)?, &request)?;
+        },
+        Commands::Move { container, from, to } => {
+            let request = Request::Move { tab: from.resolve(&ctl)?, to };
+            send_request(&ctl, container.resolve(&ctl)?, &request)?;
+        },
+        Commands::Swap { container, a, b } => {
+            let request = Request::Swap { a: a.resolve(&ctl)?, b: b.resolve(&ctl)? };
+            send_request(&ctl, container.resolve(&ctl)?, &request)?;
+        },
+        Commands::Close { wid } => {
+            let wid = wid.resolve(&ctl)?;
+            let container = ctl.conn.query_tree(wid)?.reply()?.parent;
+            send_request(&ctl, container, &Request::Close { tab: TabRef::Window(wid) })?;
+        },
+        Commands::Next { container } => {
+            let request = Request::Action { action: Action::FocusUp, tab: None };
+            send_request(&ctl, container.resolve(&ctl)?, &request)?;
+        },
+        Commands::Prev { container } => {
+            let request = Request::Action { action: Action::FocusDown, tab: None };
+            send_request(&ctl, container.resolve(&ctl)?, &request)?;
+        },
+        Commands::Action { wid, action, tab } => {
+            let tab = tab.map(|tab| tab.resolve(&ctl)).transpose()?;
+            rs_send_command(&ctl.conn, &ctl.atoms, wid.resolve(&ctl)?, &action, tab)?;
+        },
+        Commands::Group { direction } => {
+            let focused = Selector::Focused.resolve(&ctl)?;
+            let neighbor = ctl.wm.neighbor(&ctl.conn, focused, direction)?
+                .ok_or_else(|| Error::BadWindow(
+                    format!("no window to the {} of 0x{:X}", direction.name(), focused)
+                ))?;
+            transfer(&ctl, focused, neighbor)?;
+        },
+        Commands::Ungroup { container } => {
+            let container = container.resolve(&ctl)?;
+            if !is_tabbed(&ctl.conn, container)? {
+                return Err(Error::NotAContainer(container));
+            }
+            let tab = reparent_current(&ctl, container, root)?;
+            focus_detached(&ctl, tab)?;
+        },
+        Commands::Neighbor { wid, direction } => {
+            if let Some(neighbor) = ctl.wm.neighbor(&ctl.conn, wid.resolve(&ctl)?, direction)? {
+                println!("0x{:X}", neighbor);
+            }
+        },
+        Commands::Embed { wid, class, timeout } => {
+            let timeout = Some(Duration::from_secs(timeout)).filter(|t| !t.is_zero());
+            embed(&ctl, wid.resolve(&ctl)?, class.as_deref(), timeout)?;
+        },
+        Commands::Daemon { config } => {
+            let config = daemon::DaemonConfig::load(config)?;
+            daemon::run(&ctl, &config)?;
+        },
+    }
+
+    ctl.conn.flush()?;
+    Ok(())
+}
+
+
+fn create(ctl: &Ctl, wids: NonEmpty<Window>) -> Result<Window> {
+    let conn = &ctl.conn;
+    let mut to_reparent = Vec::new();
+
+    for &w in wids.iter().take(wids.len() - 1) {
+        if is_tabbed(conn, w)? {
+            to_reparent.append(&mut clients(ctl, w)?);
+        } else {
+            to_reparent.push(w);
+        }
+    }
+
+    let &last = wids.last();
+    ctl.wm.focus(conn, last)?;
+
+    // If the last window is tabbed, use it. Otherwise, spawn a new tabbed and use that
+    let tabbed = if is_tabbed(conn, last)? {
+        last
+    } else {
+        to_reparent.push(last);
+        create_tabbed(ctl)?
+    };
+
+    for &w in &to_reparent {
+        adopt(ctl, w, tabbed)?;
+    }
+
+    conn.flush()?;
+
+    Ok(tabbed)
+}
+
+
+/// Attach `wid0`, or its active tab if it's a container, to `wid1`, returning the container.
+fn transfer(ctl: &Ctl, wid0: Window, wid1: Window) -> Result<Window> {
+    let tabbed_window = create(ctl, nonempty![wid1])?;
+    if is_tabbed(&ctl.conn, wid0)? {
+        reparent_current(ctl, wid0, tabbed_window)?;
+    } else {
+        adopt(ctl, wid0, tabbed_window)?;
+    }
+    ctl.wm.focus(&ctl.conn, tabbed_window)?;
+    Ok(tabbed_window)
+}
+
+
+fn merge(ctl: &Ctl, a: Window, b: Window) -> Result<()> {
+    if a == b {
+        return Err(Error::Usage(format!("cannot merge 0x{:X} with itself", a)));
+    }
+
+    let moved = if is_tabbed(&ctl.conn, a)? { clients(ctl, a)? } else { vec![a] };
+    let active = if is_tabbed(&ctl.conn, b)? { active(ctl, b)? } else { Some(b) };
+
+    // A plain B has to be in the container before the tabs of A are attached after it
+    let container = if is_tabbed(&ctl.conn, b)? { b } else { create(ctl, nonempty![b])? };
+    create(ctl, nonempty![a, container])?;
+
+    // Attaching focuses the new tabs, so focus the old active tab again once they're all in
+    if let Some(active) = active {
+        if !wait_for_tabs(ctl, container, &moved)? {
+            let message = format!("waiting for the tabs of 0x{:X} to join 0x{:X}", a, container);
+            return Err(Error::Timeout(message));
+        }
+        send_request(ctl, container, &Request::Focus { tab: TabRef::Window(active) })?;
+    }
+    ctl.wm.focus(&ctl.conn, container)?;
+    Ok(())
+}
+
+/// Move the tabs from `at` on into a new container, returning it.
+///
+/// Without `at`, the active tab and the ones after it are moved, or all but the first tab if that
+/// is the active one.
+///
+/// Focusing the original container first makes tiling window managers put the new one next to
+/// it.
+fn split(ctl: &Ctl, container: Window, at: Option<usize>) -> Result<Window> {
+    if !is_tabbed(&ctl.conn, container)? {
+        return Err(Error::NotAContainer(container));
+    }
+
+    let tabs = clients(ctl, container)?;
+    if tabs.len() < 2 {
+        let message = format!("cannot split 0x{:X} with {} tabs", container, tabs.len());
+        return Err(Error::Usage(message));
+    }
+    let at = match at {
+        Some(at) => at,
+        None => {
+            let active = active(ctl, container)?;
+            tabs.iter().position(|&tab| Some(tab) == active).unwrap_or(tabs.len() - 1).max(1)
+        },
+    };
+    if at == 0 || at >= tabs.len() {
+        let message = format!("--at must be between 1 and {}", tabs.len() - 1);
+        return Err(Error::Usage(message));
+    }
+
+    ctl.wm.focus(&ctl.conn, container)?;
+    let new = create_tabbed(ctl)?;
+    for &tab in &tabs[at..] {
+        adopt(ctl, tab, new)?;
+    }
+    ctl.conn.flush()?;
+
+    ctl.wm.focus(&ctl.conn, new)?;
+    Ok(new)
+}
+
+/// Wait until a tabbed-rs container lists all of `tabs` in `_TABBED_RS_CLIENTS`, giving up after a
+/// second. Returns whether they all showed up.
+fn wait_for_tabs(ctl: &Ctl, container: Window, tabs: &[Window]) -> Result<bool, ReplyError> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+
+    while Instant::now() < deadline {
+        let clients = rs_get_tabbed_clients(&ctl.conn, &ctl.atoms, container)?;
+        if clients.is_some_and(|clients| tabs.iter().all(|tab| clients.contains(tab))) {
+            return Ok(true);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    Ok(false)
+}
+
+
+fn embed(ctl: &Ctl, wid: Window, class: Option<&str>, timeout: Option<Duration>) -> Result<()> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let new_wid = NewWindows::watch(ctl)?.next(ctl, class, deadline)?
+        .ok_or_else(|| Error::Timeout("waiting for a new window".to_owned()))?;
+
+    let tabbed_window = create(ctl, nonempty![wid])?;
+    adopt(ctl, new_wid, tabbed_window)?;
+    ctl.wm.focus(&ctl.conn, tabbed_window)?;
+
+    Ok(())
+}
+
+
+/// Notices new top-level windows.
+///
+/// Window managers that can report new windows, like bspwm and i3, are asked to. Otherwise new
+/// windows are noticed either when they're mapped as children of the root window or, with
+/// reparenting window managers, when they're added to `_NET_CLIENT_LIST`. The same window can
+/// show up through both, and some window managers like bspwm map windows again when switching
+/// desktops, so each window is only returned once and windows that were already there never are.
+struct NewWindows {
+    /// New windows reported by the window manager, if it can
+    subscription: Option<mpsc::Receiver<Window>>,
+    /// `_NET_CLIENT_LIST` as of the last change
+    known: Vec<Window>,
+    /// Windows that were already there or already returned
+    seen: HashSet<Window>,
+    /// Windows noticed but not returned yet
+    pending: VecDeque<Window>,
+}
+
+impl NewWindows {
+    /// Start watching; only windows that show up after this are returned.
+    fn watch(ctl: &Ctl) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let subscription = if ctl.wm.subscribe_new_windows(sender)? {
+            Some(receiver)
+        } else {
+            let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+            let attributes = ChangeWindowAttributesAux::new().event_mask(mask);
+            ctl.conn.change_window_attributes(ctl.root, &attributes)?.check()?;
+            None
+        };
+
+        let known = rs_get_window_property32(&ctl.conn, ctl.atoms._NET_CLIENT_LIST, ctl.root)?;
+        // Unmanaged children of the root window too, like windows hidden by the window manager
+        let mut seen: HashSet<Window> = selector::windows(ctl)?.into_iter().collect();
+        seen.extend(ctl.conn.query_tree(ctl.root)?.reply()?.children);
+
+        Ok(NewWindows { subscription, known, seen, pending: VecDeque::new() })
+    }
+
+    /// Wait for the next new window, optionally with the given WM_CLASS class or instance.
+    ///
+    /// Returns `None` if no window showed up before `deadline`, and waits forever without one.
+    fn next(&mut self, ctl: &Ctl, class: Option<&str>, deadline: Option<Instant>)
+    -> Result<Option<Window>> {
+        let Ctl { conn, atoms, root, .. } = ctl;
+        let root = *root;
+
+        loop {
+            if !self.pending.is_empty() {
+                self.forget_destroyed(conn)?;
+            }
+            while let Some(candidate) = self.pending.pop_front() {
+                if self.seen.contains(&candidate) {
+                    continue;
+                }
+                // Window manager frames and containers don't count, and neither does a window
+                // that was destroyed again right away.
+                let Ok((instance, window_class)) = rs_get_wm_class(conn, candidate) else { continue };
+                if window_class.is_empty() || is_tabbed(conn, candidate)? {
+                    continue;
+                }
+                if class.is_none_or(|class| class == instance || class == window_class) {
+                    self.seen.insert(candidate);
+                    return Ok(Some(candidate));
+                }
+            }
+
+            if let Some(subscription) = &self.subscription {
+                let wid = match deadline.map(|d| d.saturating_duration_since(Instant::now())) {
+                    None => subscription.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                    Some(timeout) => subscription.recv_timeout(timeout),
+                };
+                match wid {
+                    Ok(wid) => self.pending.push_back(wid),
+                    Err(mpsc::RecvTimeoutError::Timeout) => return Ok(None),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        return Err(Error::Wm("stopped reporting new windows".to_owned()));
+                    },
+                }
+                continue;
+            }
+
+            let event = match deadline {
+                None => conn.wait_for_event()?,
+                Some(deadline) => match conn.poll_for_event()? {
+                    Some(event) => event,
+                    None if Instant::now() >= deadline => return Ok(None),
+                    None => {
+                        thread::sleep(Duration::from_millis(20));
+                        continue;
+                    },
+                },
+            };
+
+            match event {
+                Event::MapNotify(e) if e.event == root && !e.override_redirect => {
+                    self.pending.push_back(e.window);
+                },
+                Event::PropertyNotify(e) if e.window == root && e.atom == atoms._NET_CLIENT_LIST => {
+                    let clients = rs_get_window_property32(conn, atoms._NET_CLIENT_LIST, root)?;
+                    self.pending.extend(clients.iter().filter(|w| !self.known.contains(w)));
+                    self.known = clients;
+                },
+                _ => {},
+            }
+        }
+    }
+
+    /// Forget windows that were destroyed, since the X server can hand their ids out again.
+    fn forget_destroyed(&mut self, conn: &RustConnection) -> Result<(), ConnectionError> {
+        let cookies = self.seen.iter()
+            .map(|&wid| Ok((wid, conn.get_window_attributes(wid)?)))
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+        for (wid, cookie) in cookies {
+            if cookie.reply().is_err() {
+                self.seen.remove(&wid);
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Whether `wid` is a container, either tabbed-rs or suckless tabbed.
+fn is_tabbed(conn: &RustConnection, wid: Window) -> Result<bool, ReplyError> {
+    Ok(Container::of(conn, wid)?.is_some())
+}
+
+
+/// The tabs of a container in order.
+///
+/// Falls back to the stacking order of the children for containers that don't publish
+/// `_TABBED_RS_CLIENTS`, like suckless tabbed.
+fn clients(ctl: &Ctl, wid: Window) -> Result<Vec<Window>, ReplyError> {
+    match rs_get_tabbed_clients(&ctl.conn, &ctl.atoms, wid)? {
+        Some(clients) => Ok(clients),
+        None => Ok(ctl.conn.query_tree(wid)?.reply()?.children),
+    }
+}
+
+/// The active tab of a container, falling back to the topmost child like [`clients`].
+fn active(ctl: &Ctl, wid: Window) -> Result<Option<Window>, ReplyError> {
+    match rs_get_tabbed_active(&ctl.conn, &ctl.atoms, wid)? {
+        Some(active) => Ok(active),
+        None => Ok(ctl.conn.query_tree(wid)?.reply()?.children.last().copied()),
+    }
+}
+
+
+/// Focus a tab that was just detached, if any.
+fn focus_detached(ctl: &Ctl, tab: Option<Window>) -> Result<(), ConnectionError> {
+    if let Some(tab) = tab {
+        ctl.conn.flush()?;
+        // The window manager may not have taken the window on yet, so this can fail
+        let _ = ctl.wm.focus(&ctl.conn, tab);
+    }
+    Ok(())
+}
+
+/// Attach a window to a container, recording where it was so it can be put back on detach.
+fn adopt(ctl: &Ctl, wid: Window, container: Window) -> Result<(), ReplyError> {
+    rs_save_geometry(&ctl.conn, &ctl.atoms, wid)?;
+    ctl.conn.reparent_window(wid, container, 0, 0)?.check()
+}
+
+/// Move a tab of `from` to the container `to`, or back where it was if `to` is the root window.
+///
+/// Returns the original hints of a window put back on the root window, for [`finish_restore`].
+fn reparent(ctl: &Ctl, wid: Window, from: Window, to: Window)
+-> Result<Option<OriginalHints>, ReplyError> {
+    if to == ctl.root {
+        Ok(Some(rs_restore_window(&ctl.conn, &ctl.atoms, wid, from)?))
+    } else {
+        adopt(ctl, wid, to)?;
+        Ok(None)
+    }
+}
+
+/// Put back the original hints of detached windows once the window manager has mapped them, or
+/// after a second for the ones it doesn't map.
+fn finish_restore(ctl: &Ctl, restored: Vec<OriginalHints>) -> Result<(), ReplyError> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut waiting = restored;
+
+    while !waiting.is_empty() && Instant::now() < deadline {
+        let mut unmapped = vec![];
+        for original in waiting {
+            match ctl.conn.get_window_attributes(original.window)?.reply() {
+                Ok(attributes) if attributes.map_state != MapState::VIEWABLE => {
+                    unmapped.push(original);
+                },
+                Ok(_) => original.restore(&ctl.conn)?,
+                // Destroyed since, so there's nothing to put back
+                Err(ReplyError::X11Error(_)) => {},
+                Err(e) => return Err(e),
+            }
+        }
+        waiting = unmapped;
+        if !waiting.is_empty() {
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    for original in waiting {
+        original.restore(&ctl.conn)?;
+    }
+    Ok(())
+}
+
+
+fn reparent_all(ctl: &Ctl, wid0: Window, wid1: Window) -> Result<Vec<Window>, ReplyError> {
+    let children = clients(ctl, wid0)?;
+
+    let mut restored = vec![];
+    for &w in &children {
+        restored.extend(reparent(ctl, w, wid0, wid1)?);
+    }
+    finish_restore(ctl, restored)?;
+
+    Ok(children)
+}
+
+
+fn reparent_current(ctl: &Ctl, wid0: Window, wid1: Window) -> Result<Option<Window>, ReplyError> {
+    if let Some(active) = active(ctl, wid0)? {
+        let restored = reparent(ctl, active, wid0, wid1)?;
+        finish_restore(ctl, restored.into_iter().collect())?;
+        Ok(Some(active))
+    } else {
+        Ok(None)
+    }
+}
+
+
+fn query(ctl: &Ctl, wid: Window) -> Result<(), ReplyError> {
+    println!("wid: {} 0x{:X}", wid, wid);
+    println!("is_tabbed: {}", is_tabbed(&ctl.conn, wid)?);
+    println!("container: {:?}", Container::of(&ctl.conn, wid)?);
+    println!("children: {:?}", clients(ctl, wid)?);
+    println!("active: {:?}", active(ctl, wid)?);
+    Ok(())
+}
+
+
+/// Every container on the display, found by walking the whole window tree since window managers
+/// may have reparented them into frames.
+fn containers(ctl: &Ctl, include_tabbed: bool) -> Result<Vec<Window>, ReplyError> {
+    let mut containers = vec![];
+    let mut stack = vec![ctl.root];
+
+    while let Some(wid) = stack.pop() {
+        match Container::of(&ctl.conn, wid)? {
+            Some(Container::TabbedRs) => containers.push(wid),
+            Some(Container::Tabbed) if include_tabbed => containers.push(wid),
+            Some(Container::Tabbed) => {},
+            // Children are pushed in reverse to list the containers in stacking order
+            None => stack.extend(ctl.conn.query_tree(wid)?.reply()?.children.into_iter().rev()),
+        }
+    }
+    Ok(containers)
+}
+
+fn list(ctl: &Ctl, json: bool, include_tabbed: bool) -> Result<()> {
+    let infos = containers(ctl, include_tabbed)?
+        .into_iter()
+        .map(|wid| ContainerInfo::fetch(ctl, wid))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if json {
+        println!("{}", serde_json::to_string(&infos).expect("container info is serializable"));
+        return Ok(());
+    }
+
+    for info in infos {
+        println!("0x{:X} {}", info.id, info.container.unwrap_or_default());
+        for tab in info.tabs {
+            let marker = if tab.active { '*' } else { ' ' };
+            println!("  {} 0x{:X} {} {}", marker, tab.window, tab.class, tab.title);
+        }
+    }
+    Ok(())
+}
+
+
+/// Send a request to a tabbed-rs container over its IPC socket.
+fn send_request(ctl: &Ctl, container: Window, request: &Request) -> Result<()> {
+    if Container::of(&ctl.conn, container)? != Some(Container::TabbedRs) {
+        return Err(Error::NotAContainer(container));
+    }
+
+    let path = rs_get_window_property8(&ctl.conn, ctl.atoms._TABBED_RS_SOCKET, container)?;
+    let path = match String::from_utf8(path) {
+        Ok(path) if !path.is_empty() => PathBuf::from(path),
+        _ => ipc::socket_path(container),
+    };
+
+    let response = ipc::request(&path, request).map_err(|e| {
+        Error::Ipc(format!("cannot reach 0x{:X} at {}: {}", container, path.display(), e))
+    })?;
+    match response.error {
+        Some(error) if !response.ok => Err(Error::Ipc(error)),
+        _ => Ok(()),
+    }
+}
+
+
+fn parse_action(s: &str) -> Result<Action, serde_json::Error> {
+    serde_json::from_str(s).or_else(|_| serde_json::from_value(s.into()))
+}
+
+
+/// Spawn a new container, which prints its window id once it's detached.
+///
+/// Both programs take `-c` to close with the last tab and `-d` to detach.
+fn create_tabbed(ctl: &Ctl) -> Result<Window> {
+    let program = ctl.container.class();
+    let mut child = Command::new(program)
+        .args(["-c", "-d"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Spawn(format!("{}: {}", program, e)))?;
+
+    // Read on a thread so a container that never prints anything can time out
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut line = String::new();
+        let _ = BufReader::new(stdout).read_line(&mut line);
+        let _ = sender.send(line);
+    });
+
+    let line = receiver.recv_timeout(ctl.spawn_timeout);
+    if line.is_err() {
+        let _ = child.kill();
+    }
+    let _ = child.wait();
+
+    let line = line.map_err(|_| Error::Timeout(format!("waiting for {} to start", program)))?;
+    line.trim()
+        .strip_prefix("0x")
+        .and_then(|id| Window::from_str_radix(id, 16).ok())
+        .ok_or_else(|| Error::Spawn(format!("unexpected output from {}: {:?}", program, line)))
+}
@@ -0,0 +1,227 @@
+//! Ways to name a window on the command line besides its id.
+
+use x11rb::protocol::xproto::*;
+
+use regex::Regex;
+
+use std::str::FromStr;
+
+use clap_num::maybe_hex;
+
+use tabbed_rs::ipc::TabRef;
+use tabbed_rs::x11::*;
+
+use crate::error::{Error, Result};
+use crate::{clients, is_tabbed, Ctl};
+
+
+#[derive(Debug, Clone)]
+pub enum Selector {
+    /// A window id, in decimal or hex with the prefix "0x"
+    Id(Window),
+    /// `focused`: the active window according to `_NET_ACTIVE_WINDOW`
+    Focused,
+    /// `pointer`: the window under the pointer
+    Pointer,
+    /// `class:NAME`: the first window whose WM_CLASS class or instance is NAME
+    Class(String),
+    /// `title:REGEX`: the first window whose title matches REGEX
+    Title(Regex),
+    /// `tabbed:N` or `tabbed:CONTAINER:N`: the Nth tab, counting from 0, of the given container or
+    /// else the focused one
+    Tab(Option<Window>, usize),
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            _ if s == "focused" => Ok(Selector::Focused),
+            _ if s == "pointer" => Ok(Selector::Pointer),
+            Some(("class", name)) => Ok(Selector::Class(name.to_owned())),
+            Some(("title", regex)) => Regex::new(regex)
+                .map(Selector::Title)
+                .map_err(|e| e.to_string()),
+            Some(("tabbed", rest)) => {
+                let index = |s: &str| s.parse().map_err(|_| format!("invalid tab index '{}'", s));
+                match rest.split_once(':') {
+                    Some((container, n)) => Ok(Selector::Tab(Some(maybe_hex(container)?), index(n)?)),
+                    None => Ok(Selector::Tab(None, index(rest)?)),
+                }
+            },
+            _ => maybe_hex(s).map(Selector::Id),
+        }
+    }
+}
+
+impl Selector {
+    /// Find the window this selects.
+    pub fn resolve(&self, ctl: &Ctl) -> Result<Window> {
+        let conn = &ctl.conn;
+
+        match self {
+            Selector::Id(wid) => Ok(*wid),
+            Selector::Focused => focused(ctl)?.ok_or_else(|| bad_window("no window is focused")),
+            Selector::Pointer => {
+                let mut wid = ctl.root;
+                // Descend through window manager frames to the first window with a WM_CLASS
+                loop {
+                    let child = conn.query_pointer(wid)?.reply()?.child;
+                    if child == x11rb::NONE {
+                        return Err(bad_window("no window under the pointer"));
+                    }
+                    wid = child;
+                    if !rs_get_wm_class(conn, wid)?.1.is_empty() {
+                        return Ok(wid);
+                    }
+                }
+            },
+            Selector::Class(name) => find(ctl, |wid| {
+                let (instance, class) = rs_get_wm_class(conn, wid)?;
+                Ok(&instance == name || &class == name)
+            })?.ok_or_else(|| bad_window(format!("no window with class {}", name))),
+            Selector::Title(regex) => find(ctl, |wid| {
+                Ok(regex.is_match(&rs_get_window_name(conn, &ctl.atoms, wid)?))
+            })?.ok_or_else(|| bad_window(format!("no window with a title matching {}", regex))),
+            Selector::Tab(container, index) => {
+                let container = match container {
+                    Some(container) => *container,
+                    None => focused(ctl)?.ok_or_else(|| bad_window("no container is focused"))?,
+                };
+                if !is_tabbed(conn, container)? {
+                    return Err(Error::NotAContainer(container));
+                }
+                clients(ctl, container)?
+                    .get(*index)
+                    .copied()
+                    .ok_or_else(|| bad_window(format!("0x{:X} has no tab {}", container, index)))
+            },
+        }
+    }
+}
+
+fn bad_window(message: impl Into<String>) -> Error {
+    Error::BadWindow(message.into())
+}
+
+fn focused(ctl: &Ctl) -> Result<Option<Window>> {
+    let active = rs_get_window_property32(&ctl.conn, ctl.atoms._NET_ACTIVE_WINDOW, ctl.root)?;
+    Ok(active.first().copied().filter(|&wid| wid != x11rb::NONE))
+}
+
+/// The first top-level window or tab for which `predicate` holds.
+fn find(ctl: &Ctl, mut predicate: impl FnMut(Window) -> Result<bool>) -> Result<Option<Window>> {
+    for wid in windows(ctl)? {
+        if predicate(wid)? {
+            return Ok(Some(wid));
+        }
+    }
+    Ok(None)
+}
+
+/// Every top-level window, followed by the tabs if it is a container.
+///
+/// Top-level windows come from `_NET_CLIENT_LIST`, or the children of the root window that have a
+/// WM_CLASS if the window manager doesn't set it.
+pub fn windows(ctl: &Ctl) -> Result<Vec<Window>> {
+    let conn = &ctl.conn;
+
+    let mut top_level = rs_get_window_property32(conn, ctl.atoms._NET_CLIENT_LIST, ctl.root)?;
+    if top_level.is_empty() {
+        for wid in conn.query_tree(ctl.root)?.reply()?.children {
+            if !rs_get_wm_class(conn, wid)?.1.is_empty() {
+                top_level.push(wid);
+            }
+        }
+    }
+
+    let mut windows = vec![];
+    for wid in top_level {
+        windows.push(wid);
+        if is_tabbed(conn, wid)? {
+            windows.extend(clients(ctl, wid)?);
+        }
+    }
+    Ok(windows)
+}
+
+
+/// A tab of a known container: a decimal index, or a window as a hex id or another selector.
+#[derive(Debug, Clone)]
+pub enum TabArg {
+    Index(usize),
+    Window(Selector),
+}
+
+impl FromStr for TabArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s.parse() {
+            Ok(index) => Ok(TabArg::Index(index)),
+            Err(_) => s.parse().map(TabArg::Window),
+        }
+    }
+}
+
+impl TabArg {
+    pub fn resolve(&self, ctl: &Ctl) -> Result<TabRef> {
+        match self {
+            TabArg::Index(index) => Ok(TabRef::Index(*index)),
+            TabArg::Window(selector) => Ok(TabRef::Window(selector.resolve(ctl)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::Cli;
+
+    #[test]
+    fn ids_are_decimal_or_hex() {
+        assert!(matches!("26".parse(), Ok(Selector::Id(26))));
+        assert!(matches!("0x1A".parse(), Ok(Selector::Id(26))));
+        assert!("1A".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn keywords() {
+        assert!(matches!("focused".parse(), Ok(Selector::Focused)));
+        assert!(matches!("pointer".parse(), Ok(Selector::Pointer)));
+        assert!(matches!("class:st".parse(), Ok(Selector::Class(name)) if name == "st"));
+        assert!(matches!("title:^vi".parse(), Ok(Selector::Title(regex)) if regex.is_match("vim")));
+    }
+
+    #[test]
+    fn tabs() {
+        assert!(matches!("tabbed:2".parse(), Ok(Selector::Tab(None, 2))));
+        assert!(matches!("tabbed:0x1A:2".parse(), Ok(Selector::Tab(Some(26), 2))));
+        assert!(matches!("tabbed:26:0".parse(), Ok(Selector::Tab(Some(26), 0))));
+        assert!("tabbed:x".parse::<Selector>().is_err());
+        assert!("tabbed:26:x".parse::<Selector>().is_err());
+        assert!("tabbed:x:2".parse::<Selector>().is_err());
+    }
+
+    #[test]
+    fn bad_regex_is_a_usage_error() {
+        assert!("title:(".parse::<Selector>().is_err());
+
+        let e = Cli::try_parse_from(["tabctrl", "query", "title:("]).unwrap_err();
+        assert_eq!(e.kind(), clap::error::ErrorKind::ValueValidation);
+        assert_eq!(e.exit_code(), Error::Usage(String::new()).exit_code() as i32);
+    }
+
+    #[test]
+    fn bare_decimal_tab_is_an_index() {
+        assert!(matches!("3".parse(), Ok(TabArg::Index(3))));
+        assert!(matches!("0x3".parse(), Ok(TabArg::Window(Selector::Id(3)))));
+        assert!(matches!("focused".parse(), Ok(TabArg::Window(Selector::Focused))));
+        assert!(matches!("tabbed:1".parse(), Ok(TabArg::Window(Selector::Tab(None, 1)))));
+        assert!("title:(".parse::<TabArg>().is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! The window manager specific parts of tabctrl.
+
+use x11rb::protocol::xproto::*;
+use x11rb::rust_connection::RustConnection;
+
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use clap::ValueEnum;
+
+use tabbed_rs::x11::*;
+
+use crate::error::Error;
+
+
+mod i3;
+pub use i3::I3;
+
+
+pub type WmResult<T> = Result<T, Error>;
+
+fn wm_error(e: impl std::fmt::Display) -> Error {
+    Error::Wm(e.to_string())
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    West,
+    South,
+    North,
+    East,
+}
+
+impl Direction {
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::West => "west",
+            Direction::South => "south",
+            Direction::North => "north",
+            Direction::East => "east",
+        }
+    }
+}
+
+/// Which backend to use, see [`backend`].
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WmChoice {
+    /// Pick a backend based on the running window manager
+    Auto,
+    Bspwm,
+    /// i3 or sway, through their IPC socket
+    I3,
+    /// Any window manager following the EWMH spec
+    Ewmh,
+}
+
+
+/// What tabctrl needs from the window manager.
+pub trait WmBackend {
+    /// Focus a top-level window.
+    fn focus(&self, conn: &RustConnection, wid: Window) -> WmResult<()>;
+
+    /// The top-level window next to `wid` in `direction`, if there is one.
+    fn neighbor(&self, conn: &RustConnection, wid: Window, direction: Direction)
+        -> WmResult<Option<Window>>;
+
+    /// Send the windows the window manager takes on from now on to `sender`, from a background
+    /// thread, until the receiver hangs up.
+    ///
+    /// Returns `false` if the window manager can't report new windows, in which case they have to
+    /// be noticed over X11.
+    fn subscribe_new_windows(&self, sender: mpsc::Sender<Window>) -> WmResult<bool>;
+}
+
+/// Create the chosen backend, detecting the window manager through `_NET_SUPPORTING_WM_CHECK`
+/// for [`WmChoice::Auto`].
+pub fn backend(conn: &RustConnection, atoms: &Atoms, root: Window, choice: WmChoice)
+-> WmResult<Box<dyn WmBackend>> {
+    let choice = match choice {
+        WmChoice::Auto => match wm_name(conn, atoms, root)?.as_deref() {
+            Some("bspwm") => WmChoice::Bspwm,
+            Some("i3") => WmChoice::I3,
+            // sway doesn't set _NET_SUPPORTING_WM_CHECK for Xwayland clients
+            _ if std::env::var_os("SWAYSOCK").is_some() => WmChoice::I3,
+            _ => WmChoice::Ewmh,
+        },
+        choice => choice,
+    };
+
+    Ok(match choice {
+        WmChoice::Bspwm => Box::new(Bspwm),
+        WmChoice::I3 => Box::new(I3::connect()?),
+        _ => Box::new(Ewmh { atoms: *atoms, root }),
+    })
+}
+
+/// The name the window manager gives itself on its `_NET_SUPPORTING_WM_CHECK` window.
+fn wm_name(conn: &RustConnection, atoms: &Atoms, root: Window) -> WmResult<Option<String>> {
+    let check = rs_get_window_property32(conn, atoms._NET_SUPPORTING_WM_CHECK, root)?;
+    match check.first() {
+        Some(&wm_window) => Ok(Some(rs_get_window_name(conn, atoms, wm_window)?)),
+        None => Ok(None),
+    }
+}
+
+
+/// bspwm, driven through `bspc`.
+pub struct Bspwm;
+
+impl Bspwm {
+    fn bspc(args: &[&str]) -> WmResult<String> {
+        let output = Command::new("bspc").args(args).output()
+            .map_err(|e| Error::Wm(format!("cannot run bspc: {}", e)))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(Error::Wm(format!("bspc {}: {}", args.join(" "), stderr.trim())));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl WmBackend for Bspwm {
+    fn focus(&self, _conn: &RustConnection, wid: Window) -> WmResult<()> {
+        Bspwm::bspc(&["node", &wid.to_string(), "--focus"]).map(drop)
+    }
+
+    fn neighbor(&self, _conn: &RustConnection, wid: Window, direction: Direction)
+    -> WmResult<Option<Window>> {
+        let selector = format!("{}#{}", wid, direction.name());
+        match Bspwm::bspc(&["query", "-N", "-n", &selector]) {
+            Ok(output) => Ok(output.lines().next().and_then(parse_hex)),
+            // bspc fails when the selector matches nothing
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn subscribe_new_windows(&self, sender: mpsc::Sender<Window>) -> WmResult<bool> {
+        let mut child = Command::new("bspc")
+            .args(["subscribe", "node_add"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Wm(format!("cannot run bspc: {}", e)))?;
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        thread::spawn(move || {
+            // node_add <monitor_id> <desktop_id> <ip_id> <node_id>
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                let wid = line.split_whitespace().nth(4).and_then(parse_hex);
+                if wid.is_some_and(|wid| sender.send(wid).is_err()) {
+                    break;
+                }
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+        Ok(true)
+    }
+}
+
+fn parse_hex(s: &str) -> Option<Window> {
+    Window::from_str_radix(s.trim().strip_prefix("0x")?, 16).ok()
+}
+
+
+/// Any EWMH compliant window manager.
+pub struct Ewmh {
+    atoms: Atoms,
+    root: Window,
+}
+
+impl Ewmh {
+    fn client_list(&self, conn: &RustConnection) -> WmResult<Vec<Window>> {
+        Ok(rs_get_window_property32(conn, self.atoms._NET_CLIENT_LIST, self.root)?)
+    }
+
+    fn desktop(&self, conn: &RustConnection, wid: Window) -> WmResult<Option<u32>> {
+        Ok(rs_get_window_property32(conn, self.atoms._NET_WM_DESKTOP, wid)?.first().copied())
+    }
+
+    /// The position and size of a window relative to the root window.
+    fn geometry(&self, conn: &RustConnection, wid: Window) -> WmResult<Rect> {
+        let geometry = conn.get_geometry(wid)?.reply()?;
+        let origin = conn.translate_coordinates(wid, self.root, 0, 0)?.reply()?;
+        Ok((origin.dst_x as i32, origin.dst_y as i32, geometry.width as i32, geometry.height as i32))
+    }
+}
+
+impl WmBackend for Ewmh {
+    fn focus(&self, conn: &RustConnection, wid: Window) -> WmResult<()> {
+        Ok(rs_request_focus(conn, &self.atoms, self.root, wid)?)
+    }
+
+    /// The closest visible window in `direction` on the same desktop.
+    fn neighbor(&self, conn: &RustConnection, wid: Window, direction: Direction)
+    -> WmResult<Option<Window>> {
+        let desktop = self.desktop(conn, wid)?;
+        let rect = self.geometry(conn, wid)?;
+
+        let mut candidates = vec![];
+        for other in self.client_list(conn)? {
+            if other == wid || self.desktop(conn, other)? != desktop {
+                continue;
+            }
+            let attributes = conn.get_window_attributes(other)?.reply()?;
+            if attributes.map_state == MapState::VIEWABLE {
+                candidates.push((other, self.geometry(conn, other)?));
+            }
+        }
+
+        Ok(closest(rect, direction, candidates))
+    }
+
+    /// There's no EWMH notification for new windows besides `_NET_CLIENT_LIST` changing, which
+    /// [`NewWindows`](crate::NewWindows) watches itself.
+    fn subscribe_new_windows(&self, _sender: mpsc::Sender<Window>) -> WmResult<bool> {
+        Ok(false)
+    }
+}
+
+
+/// A window's position and size as `(x, y, width, height)`.
+pub type Rect = (i32, i32, i32, i32);
+
+/// The window closest to `rect` in `direction` that overlaps with it on the other axis.
+fn closest(rect: Rect, direction: Direction, candidates: Vec<(Window, Rect)>) -> Option<Window> {
+    let (x, y, w, h) = rect;
+
+    let mut best = None;
+    for (other, (ox, oy, ow, oh)) in candidates {
+        let overlaps_x = ox < x + w && x < ox + ow;
+        let overlaps_y = oy < y + h && y < oy + oh;
+
+        let distance = match direction {
+            Direction::West if overlaps_y && ox + ow <= x => x - (ox + ow),
+            Direction::East if overlaps_y && ox >= x + w => ox - (x + w),
+            Direction::North if overlaps_x && oy + oh <= y => y - (oy + oh),
+            Direction::South if overlaps_x && oy >= y + h => oy - (y + h),
+            _ => continue,
+        };
+
+        if best.is_none_or(|(d, _)| distance < d) {
+            best = Some((distance, other));
+        }
+    }
+
+    best.map(|(_, other)| other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTER: Rect = (100, 100, 100, 100);
+
+    #[test]
+    fn closest_in_each_direction() {
+        let candidates = vec![
+            (1, (0, 100, 50, 100)),
+            (2, (250, 100, 50, 100)),
+            (3, (100, 0, 100, 50)),
+            (4, (100, 250, 100, 50)),
+        ];
+
+        assert_eq!(closest(CENTER, Direction::West, candidates.clone()), Some(1));
+        assert_eq!(closest(CENTER, Direction::East, candidates.clone()), Some(2));
+        assert_eq!(closest(CENTER, Direction::North, candidates.clone()), Some(3));
+        assert_eq!(closest(CENTER, Direction::South, candidates), Some(4));
+    }
+
+    #[test]
+    fn closest_prefers_the_nearer_window() {
+        let candidates = vec![(1, (300, 100, 50, 100)), (2, (200, 150, 50, 100))];
+        assert_eq!(closest(CENTER, Direction::East, candidates), Some(2));
+    }
+
+    #[test]
+    fn closest_keeps_the_first_of_a_tie() {
+        let candidates = vec![(1, (250, 100, 50, 50)), (2, (250, 150, 50, 50))];
+        assert_eq!(closest(CENTER, Direction::East, candidates), Some(1));
+        let candidates = vec![(2, (250, 150, 50, 50)), (1, (250, 100, 50, 50))];
+        assert_eq!(closest(CENTER, Direction::East, candidates), Some(2));
+    }
+
+    #[test]
+    fn closest_needs_overlap_on_the_other_axis() {
+        // Diagonal, only touching the corner, and overlapping the window itself
+        let candidates = vec![
+            (1, (250, 250, 50, 50)),
+            (2, (200, 200, 50, 50)),
+            (3, (150, 100, 100, 100)),
+        ];
+        assert_eq!(closest(CENTER, Direction::East, candidates.clone()), None);
+        assert_eq!(closest(CENTER, Direction::South, candidates), None);
+        assert_eq!(closest(CENTER, Direction::West, vec![]), None);
+    }
+}
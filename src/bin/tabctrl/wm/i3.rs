@@ -0,0 +1,374 @@
+//! i3 and sway, through the IPC protocol they share.
+//!
+//! Every message is the magic string `i3-ipc`, the payload length and message type as native
+//! endian u32s, and then a JSON payload.
+
+use x11rb::protocol::xproto::Window;
+use x11rb::rust_connection::RustConnection;
+
+use serde::Deserialize;
+
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::error::Error;
+use super::{closest, wm_error, Direction, Rect, WmBackend, WmResult};
+
+
+const MAGIC: &[u8] = b"i3-ipc";
+
+const RUN_COMMAND: u32 = 0;
+const SUBSCRIBE: u32 = 2;
+const GET_TREE: u32 = 4;
+
+/// Events have the high bit set on top of their number
+const WINDOW_EVENT: u32 = 1 << 31 | 3;
+
+
+#[derive(Deserialize)]
+struct CommandReply {
+    success: bool,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// A `window` event, sent when a window is created, focused, closed and so on.
+#[derive(Deserialize)]
+struct WindowEvent {
+    change: String,
+    container: EventContainer,
+}
+
+#[derive(Deserialize)]
+struct EventContainer {
+    /// The X11 window, missing for native Wayland windows
+    #[serde(default)]
+    window: Option<Window>,
+}
+
+/// A container in the layout tree, only with what we need.
+#[derive(Deserialize)]
+struct Node {
+    #[serde(rename = "type")]
+    kind: String,
+    /// The X11 window, missing for containers and native Wayland windows
+    #[serde(default)]
+    window: Option<Window>,
+    rect: NodeRect,
+    #[serde(default)]
+    nodes: Vec<Node>,
+    #[serde(default)]
+    floating_nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct NodeRect {
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+impl Node {
+    fn children(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().chain(&self.floating_nodes)
+    }
+
+    /// The workspace that holds `wid`.
+    fn workspace_of(&self, wid: Window) -> Option<&Node> {
+        self.children().find_map(|child| match child.kind.as_str() {
+            "workspace" if child.contains(wid) => Some(child),
+            "workspace" => None,
+            _ => child.workspace_of(wid),
+        })
+    }
+
+    fn contains(&self, wid: Window) -> bool {
+        self.window == Some(wid) || self.children().any(|child| child.contains(wid))
+    }
+
+    fn windows(&self, windows: &mut Vec<(Window, Rect)>) {
+        if let Some(wid) = self.window {
+            let NodeRect { x, y, width, height } = self.rect;
+            windows.push((wid, (x, y, width, height)));
+        }
+        for child in self.children() {
+            child.windows(windows);
+        }
+    }
+}
+
+
+pub struct I3 {
+    socket: PathBuf,
+}
+
+impl I3 {
+    /// Find the IPC socket through `$I3SOCK`, `$SWAYSOCK` or `i3 --get-socketpath`.
+    pub fn connect() -> WmResult<Self> {
+        let socket = match std::env::var_os("I3SOCK").or_else(|| std::env::var_os("SWAYSOCK")) {
+            Some(path) => PathBuf::from(path),
+            None => {
+                let output = Command::new("i3").arg("--get-socketpath").output()
+                    .map_err(|e| Error::Wm(format!("cannot find the i3 socket: {}", e)))?;
+                let path = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+                // Without a running i3 there is no path, only an error
+                if !output.status.success() || path.is_empty() {
+                    return Err(Error::Wm("no i3/sway socket found".to_owned()));
+                }
+                PathBuf::from(path)
+            },
+        };
+        Ok(I3 { socket })
+    }
+
+    fn send(stream: &mut UnixStream, kind: u32, payload: &str) -> WmResult<()> {
+        let mut message = MAGIC.to_vec();
+        message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+        message.extend_from_slice(&kind.to_ne_bytes());
+        message.extend_from_slice(payload.as_bytes());
+        stream.write_all(&message).map_err(wm_error)
+    }
+
+    fn receive(stream: &mut UnixStream) -> WmResult<(u32, Vec<u8>)> {
+        let mut header = [0; 14];
+        stream.read_exact(&mut header).map_err(wm_error)?;
+        if &header[..6] != MAGIC {
+            return Err(Error::Wm("invalid i3 ipc message".to_owned()));
+        }
+
+        let len = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]);
+        let kind = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+
+        let mut payload = vec![0; len as usize];
+        stream.read_exact(&mut payload).map_err(wm_error)?;
+        Ok((kind, payload))
+    }
+
+    fn stream(&self) -> WmResult<UnixStream> {
+        UnixStream::connect(&self.socket)
+            .map_err(|e| Error::Wm(format!("cannot connect to {}: {}", self.socket.display(), e)))
+    }
+
+    fn request(&self, kind: u32, payload: &str) -> WmResult<Vec<u8>> {
+        let mut stream = self.stream()?;
+        I3::send(&mut stream, kind, payload)?;
+        Ok(I3::receive(&mut stream)?.1)
+    }
+
+    /// Subscribe to `window` events on a connection of its own, and pass the X11 window of every
+    /// new one on to `sender` until it hangs up.
+    fn subscribe_windows(&self, sender: mpsc::Sender<Window>) -> WmResult<()> {
+        let mut stream = self.stream()?;
+        I3::send(&mut stream, SUBSCRIBE, r#"["window"]"#)?;
+        let reply: CommandReply = serde_json::from_slice(&I3::receive(&mut stream)?.1)
+            .map_err(wm_error)?;
+        if !reply.success {
+            return Err(Error::Wm("cannot subscribe to window events".to_owned()));
+        }
+
+        thread::spawn(move || {
+            while let Ok((kind, payload)) = I3::receive(&mut stream) {
+                if kind != WINDOW_EVENT {
+                    continue;
+                }
+                let Ok(event) = serde_json::from_slice::<WindowEvent>(&payload) else { continue };
+                if event.change != "new" {
+                    continue;
+                }
+                if event.container.window.is_some_and(|wid| sender.send(wid).is_err()) {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn run_command(&self, command: &str) -> WmResult<()> {
+        let replies: Vec<CommandReply> = serde_json::from_slice(&self.request(RUN_COMMAND, command)?)
+            .map_err(wm_error)?;
+        match replies.into_iter().find(|reply| !reply.success) {
+            Some(reply) => Err(Error::Wm(reply.error.unwrap_or_else(|| format!("{} failed", command)))),
+            None => Ok(()),
+        }
+    }
+
+    /// The closest window in `direction` on the same workspace.
+    fn neighbor_of(&self, wid: Window, direction: Direction) -> WmResult<Option<Window>> {
+        let tree: Node = serde_json::from_slice(&self.request(GET_TREE, "")?).map_err(wm_error)?;
+
+        let mut windows = vec![];
+        if let Some(workspace) = tree.workspace_of(wid) {
+            workspace.windows(&mut windows);
+        }
+
+        let rect = match windows.iter().find(|(w, _)| *w == wid) {
+            Some(&(_, rect)) => rect,
+            None => return Ok(None),
+        };
+        windows.retain(|(w, _)| *w != wid);
+
+        Ok(closest(rect, direction, windows))
+    }
+}
+
+impl WmBackend for I3 {
+    fn focus(&self, _conn: &RustConnection, wid: Window) -> WmResult<()> {
+        self.run_command(&format!("[id={}] focus", wid))
+    }
+
+    fn neighbor(&self, _conn: &RustConnection, wid: Window, direction: Direction)
+    -> WmResult<Option<Window>> {
+        self.neighbor_of(wid, direction)
+    }
+
+    fn subscribe_new_windows(&self, sender: mpsc::Sender<Window>) -> WmResult<bool> {
+        self.subscribe_windows(sender).map(|_| true)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::os::unix::net::UnixListener;
+
+    /// A stand-in for i3 that answers one request per connection with the next of `replies`,
+    /// passing on the type and payload of each request it got. Any messages after a reply in
+    /// the same conversation are sent right after it, like events on a subscription.
+    struct FakeI3 {
+        dir: PathBuf,
+        requests: mpsc::Receiver<(u32, String)>,
+    }
+
+    impl FakeI3 {
+        fn start(name: &str, replies: Vec<(u32, Vec<u8>)>) -> (I3, FakeI3) {
+            FakeI3::converse(name, replies.into_iter().map(|reply| vec![reply]).collect())
+        }
+
+        fn converse(name: &str, conversations: Vec<Vec<(u32, Vec<u8>)>>) -> (I3, FakeI3) {
+            let dir = std::env::temp_dir()
+                .join(format!("tabctrl-i3-test-{}-{}", std::process::id(), name));
+            std::fs::create_dir_all(&dir).unwrap();
+            let socket = dir.join("ipc.sock");
+            let listener = UnixListener::bind(&socket).unwrap();
+
+            let (sender, requests) = mpsc::channel();
+            thread::spawn(move || {
+                for messages in conversations {
+                    let (mut stream, _) = listener.accept().unwrap();
+
+                    let mut header = [0; 14];
+                    stream.read_exact(&mut header).unwrap();
+                    assert_eq!(&header[..6], b"i3-ipc");
+                    let len = u32::from_ne_bytes(header[6..10].try_into().unwrap());
+                    let request = u32::from_ne_bytes(header[10..14].try_into().unwrap());
+                    let mut body = vec![0; len as usize];
+                    stream.read_exact(&mut body).unwrap();
+                    sender.send((request, String::from_utf8(body).unwrap())).unwrap();
+
+                    for (kind, payload) in messages {
+                        let mut reply = b"i3-ipc".to_vec();
+                        reply.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+                        reply.extend_from_slice(&kind.to_ne_bytes());
+                        reply.extend_from_slice(&payload);
+                        stream.write_all(&reply).unwrap();
+                    }
+                }
+            });
+
+            (I3 { socket }, FakeI3 { dir, requests })
+        }
+
+        fn request(&self) -> (u32, String) {
+            self.requests.recv().unwrap()
+        }
+    }
+
+    impl Drop for FakeI3 {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn run_command() {
+        let (i3, fake) = FakeI3::start("run", vec![(RUN_COMMAND, br#"[{"success":true}]"#.to_vec())]);
+        i3.run_command("[id=5] focus").unwrap();
+        assert_eq!(fake.request(), (RUN_COMMAND, "[id=5] focus".to_owned()));
+    }
+
+    #[test]
+    fn run_command_failure() {
+        let reply = br#"[{"success":false,"error":"No window matches given criteria"}]"#;
+        let (i3, _fake) = FakeI3::start("fail", vec![(RUN_COMMAND, reply.to_vec())]);
+        match i3.run_command("[id=5] focus") {
+            Err(Error::Wm(e)) => assert_eq!(e, "No window matches given criteria"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_reply() {
+        let (i3, _fake) = FakeI3::start("invalid", vec![(RUN_COMMAND, b"not json".to_vec())]);
+        assert!(matches!(i3.run_command("nop"), Err(Error::Wm(_))));
+    }
+
+    #[test]
+    fn neighbors_on_the_same_workspace() {
+        let node = |window: u32, x: i32| format!(
+            r#"{{"type":"con","window":{},"rect":{{"x":{},"y":0,"width":100,"height":100}}}}"#,
+            window, x,
+        );
+        let workspace = |nodes: Vec<String>| format!(
+            r#"{{"type":"workspace","rect":{{"x":0,"y":0,"width":1000,"height":100}},"nodes":[{}]}}"#,
+            nodes.join(","),
+        );
+        let tree = format!(
+            r#"{{"type":"root","rect":{{"x":0,"y":0,"width":1000,"height":100}},"nodes":[{},{}]}}"#,
+            workspace(vec![node(1, 0), node(2, 100), node(3, 300)]),
+            workspace(vec![node(4, 200)]),
+        );
+        let replies = vec![(GET_TREE, tree.into_bytes()); 3];
+        let (i3, fake) = FakeI3::start("tree", replies);
+
+        assert_eq!(i3.neighbor_of(1, Direction::East).unwrap(), Some(2));
+        assert_eq!(fake.request(), (GET_TREE, String::new()));
+        // Window 4 is closer, but on another workspace
+        assert_eq!(i3.neighbor_of(2, Direction::East).unwrap(), Some(3));
+        assert_eq!(i3.neighbor_of(1, Direction::West).unwrap(), None);
+    }
+
+    #[test]
+    fn new_windows_from_window_events() {
+        let event = |change: &str, window: &str| (WINDOW_EVENT, format!(
+            r#"{{"change":"{}","container":{{"id":1,"window":{}}}}}"#,
+            change, window,
+        ).into_bytes());
+        let conversation = vec![
+            (SUBSCRIBE, br#"{"success":true}"#.to_vec()),
+            event("focus", "7"),
+            event("new", "null"),
+            (1 << 31 | 2, br#"{"change":"new"}"#.to_vec()),
+            event("new", "9"),
+        ];
+        let (i3, fake) = FakeI3::converse("subscribe", vec![conversation]);
+
+        let (sender, receiver) = mpsc::channel();
+        assert!(i3.subscribe_new_windows(sender).unwrap());
+        assert_eq!(fake.request(), (SUBSCRIBE, r#"["window"]"#.to_owned()));
+        assert_eq!(receiver.recv().unwrap(), 9);
+    }
+
+    #[test]
+    fn refused_subscription() {
+        let reply = vec![(SUBSCRIBE, br#"{"success":false}"#.to_vec())];
+        let (i3, _fake) = FakeI3::start("refused", reply);
+        assert!(matches!(i3.subscribe_new_windows(mpsc::channel().0), Err(Error::Wm(_))));
+    }
+}
@@ -1,121 +1,615 @@
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::{self, Unexpected, Visitor};
 use lazy_static::lazy_static;
+use regex::Regex;
 
-use figment::{Figment, providers::{Serialized, Format, Toml}};
+use figment::{Figment, Metadata, Profile, Provider, Source};
+use figment::providers::{Env, Format, Serialized, Toml};
+use figment::value::{Dict, Map, Value};
 
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::path::PathBuf;
 use std::env;
 use std::ffi::OsString;
 use std::fs::read_to_string;
+use std::fmt;
+use std::str::FromStr;
 
-//use x11rb::protocol::xproto::KeyButMask as Mod;
-use x11rb_protocol::protocol::xproto::KeyButMask;
-use x11rb_protocol::protocol::xproto::ModMask;
+use x11rb_protocol::protocol::xproto::{Keycode, Keysym, ModMask};
+
+use crate::keysym::*;
+
+mod check;
+pub use check::{Diagnostic, check_config};
 
 
 lazy_static! {
     pub static ref DEFAULT_CONFIG: Config = Config {
+        include: vec![],
         keybinds: vec![
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 43, action: Action::FocusDown },
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 44, action: Action::ShiftDown },
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 45, action: Action::ShiftUp },
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 46, action: Action::FocusUp },
-
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 22, action: Action::DetachFocused },
-            Keybind { modifiers: vec![Mod::SHIFT, Mod::CONTROL], key: 9,  action: Action::DetachAll },
-
-            Keybind { modifiers: vec![Mod::CONTROL], key: 10, action: Action::Focus(0) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 11, action: Action::Focus(1) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 12, action: Action::Focus(2) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 13, action: Action::Focus(3) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 14, action: Action::Focus(4) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 15, action: Action::Focus(5) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 16, action: Action::Focus(6) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 17, action: Action::Focus(7) },
-            Keybind { modifiers: vec![Mod::CONTROL], key: 18, action: Action::Focus(8) },
-
-            //Keybind { modifiers: vec![Mod::CONTROL], key: 19, action: Action::Focus(9) },
+            bind("Ctrl+Shift+h", Action::FocusDown),
+            bind("Ctrl+Shift+j", Action::ShiftDown),
+            bind("Ctrl+Shift+k", Action::ShiftUp),
+            bind("Ctrl+Shift+l", Action::FocusUp),
+
+            bind("Ctrl+Shift+BackSpace", Action::DetachFocused),
+            bind("Ctrl+Shift+Escape", Action::DetachAll),
+
+            bind("Ctrl+1", Action::Focus(0)),
+            bind("Ctrl+2", Action::Focus(1)),
+            bind("Ctrl+3", Action::Focus(2)),
+            bind("Ctrl+4", Action::Focus(3)),
+            bind("Ctrl+5", Action::Focus(4)),
+            bind("Ctrl+6", Action::Focus(5)),
+            bind("Ctrl+7", Action::Focus(6)),
+            bind("Ctrl+8", Action::Focus(7)),
+            bind("Ctrl+9", Action::Focus(8)),
+
+            //bind("Ctrl+0", Action::Focus(9)),
 
         ],
+        modes: BTreeMap::new(),
+        mousebinds: vec![
+            Mousebind { button: 0, modifiers: vec![], region: Region::Tab, action: Action::Select },
+        ],
+        rules: vec![],
         auto_attach: false,
         colors: true,
         font: None,
     };
 }
 
+fn bind(chord: &str, action: Action) -> Keybind {
+    Keybind {
+        modifiers: vec![],
+        key: chord.parse().expect("invalid default keybind"),
+        action,
+    }
+}
 
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+
+/// Something to do in response to a keybind or mousebind.
+///
+/// Actions that operate on a single tab apply to the focused tab for keybinds and to the tab
+/// under the pointer for mousebinds.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 pub enum Action {
     FocusUp,
     FocusDown,
     ShiftUp,
     ShiftDown,
     Focus(usize),
+    /// Focus the tab the action applies to, e.g. the clicked one
+    Select,
+    MoveFirst,
+    MoveLast,
+    /// Ask the tab's window to close
+    Close,
     DetachFocused,
     DetachAll,
     ToggleAutoAttach,
+    /// Enter the named mode from `Config::modes`
+    EnterMode(String),
+    ExitMode,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+const ANY_MASK: u16 = 1 << 15;
+
+/// A modifier key.
+///
+/// `ALT`, `SUPER` and `HYPER` are looked up in the server's modifier mapping, while `MOD1` to
+/// `MOD5` refer to the raw modifier bits. `ANY` matches regardless of the modifier state.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
 pub enum Mod {
     SHIFT,
     LOCK,
     CONTROL,
     ALT,
+    SUPER,
+    HYPER,
+    MOD1,
+    MOD2,
+    MOD3,
+    MOD4,
+    MOD5,
     ANY,
 }
 
-impl From<Mod> for u16 {
-    fn from(input: Mod) -> u16 {
-        match input {
-            Mod::SHIFT => 1 << 0,
-            Mod::LOCK => 1 << 1,
-            Mod::CONTROL => 1 << 2,
-            Mod::ALT => 1 << 3,
-            Mod::ANY => 1 << 15,
+impl Mod {
+    /// The modifier mask of this modifier, or `None` if it isn't bound to any modifier bit.
+    pub fn mask(self, mapping: &KeyboardMapping) -> Option<u16> {
+        match self {
+            Mod::SHIFT => Some(1 << 0),
+            Mod::LOCK => Some(1 << 1),
+            Mod::CONTROL => Some(1 << 2),
+            Mod::ALT => mapping.modifier_mask(&[XK_ALT_L, XK_ALT_R]).or(Some(1 << 3)),
+            Mod::SUPER => mapping.modifier_mask(&[XK_SUPER_L, XK_SUPER_R]).or(Some(1 << 6)),
+            Mod::HYPER => mapping.modifier_mask(&[XK_HYPER_L, XK_HYPER_R]),
+            Mod::MOD1 => Some(1 << 3),
+            Mod::MOD2 => Some(1 << 4),
+            Mod::MOD3 => Some(1 << 5),
+            Mod::MOD4 => Some(1 << 6),
+            Mod::MOD5 => Some(1 << 7),
+            Mod::ANY => Some(ANY_MASK),
+        }
+    }
+}
+
+impl FromStr for Mod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "shift" => Ok(Mod::SHIFT),
+            "lock" => Ok(Mod::LOCK),
+            "ctrl" | "control" => Ok(Mod::CONTROL),
+            "alt" => Ok(Mod::ALT),
+            "super" => Ok(Mod::SUPER),
+            "hyper" => Ok(Mod::HYPER),
+            "mod1" => Ok(Mod::MOD1),
+            "mod2" => Ok(Mod::MOD2),
+            "mod3" => Ok(Mod::MOD3),
+            "mod4" => Ok(Mod::MOD4),
+            "mod5" => Ok(Mod::MOD5),
+            "any" => Ok(Mod::ANY),
+            _ => Err(format!("unknown modifier '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Mod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Mod::SHIFT => "Shift",
+            Mod::LOCK => "Lock",
+            Mod::CONTROL => "Ctrl",
+            Mod::ALT => "Alt",
+            Mod::SUPER => "Super",
+            Mod::HYPER => "Hyper",
+            Mod::MOD1 => "Mod1",
+            Mod::MOD2 => "Mod2",
+            Mod::MOD3 => "Mod3",
+            Mod::MOD4 => "Mod4",
+            Mod::MOD5 => "Mod5",
+            Mod::ANY => "Any",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Combine modifiers into a single mask, failing on the first one that isn't bound.
+pub fn modifier_mask(
+    modifiers: impl IntoIterator<Item = Mod>,
+    mapping: &KeyboardMapping,
+) -> Result<u16, String> {
+    modifiers.into_iter().try_fold(0, |acc, m| match m.mask(mapping) {
+        Some(mask) => Ok(acc | mask),
+        None => Err(format!("{} is not bound to any modifier", m)),
+    })
+}
+
+/// The modifier masks to grab for a bind with modifier `mask`, one per combination of lock
+/// modifiers that may additionally be active.
+pub fn grab_masks(mask: u16, mapping: &KeyboardMapping) -> Vec<u16> {
+    if mask & ANY_MASK != 0 {
+        return vec![ANY_MASK];
+    }
+    let locks = mapping.lock_mask() & !mask;
+    lock_combinations(locks).into_iter().map(|locks| mask | locks).collect()
+}
+
+/// Whether an event's modifier `state` triggers a bind with modifier `mask`.
+///
+/// Pointer buttons and any lock modifiers the bind doesn't ask for are ignored.
+pub fn modifiers_match(mask: u16, state: u16, mapping: &KeyboardMapping) -> bool {
+    if mask & ANY_MASK != 0 {
+        return true;
+    }
+    let ignored = mapping.lock_mask() & !mask;
+    state & 0xff & !ignored == mask
+}
+
+
+/// A physical key, either as a raw keycode or as the keysym it should produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Code(Keycode),
+    Sym(Keysym),
+}
+
+/// A key together with the modifiers written in front of it, e.g. `Ctrl+Shift+h`.
+///
+/// In the configuration this is either a keysym chord string or a raw keycode integer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chord {
+    pub modifiers: Vec<Mod>,
+    pub key: Key,
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The key itself may be a literal '+', as in "Ctrl++"
+        let (modifiers, key) = match s.strip_suffix("++") {
+            Some(modifiers) => (Some(modifiers), "+"),
+            None => match s.rsplit_once('+') {
+                Some((modifiers, key)) if !key.is_empty() => (Some(modifiers), key),
+                _ => (None, s),
+            },
+        };
+
+        let modifiers = match modifiers {
+            Some(modifiers) => modifiers.split('+').map(str::parse).collect::<Result<_, _>>()?,
+            None => vec![],
+        };
+        let key = keysym_from_name(key)
+            .map(Key::Sym)
+            .ok_or_else(|| format!("unknown keysym '{}'", key))?;
+
+        Ok(Chord { modifiers, key })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier)?;
+        }
+        match self.key {
+            Key::Code(code) => write!(f, "{}", code),
+            Key::Sym(sym) => f.write_str(&keysym_name(sym)),
         }
     }
 }
 
+impl Serialize for Chord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.key {
+            Key::Code(code) if self.modifiers.is_empty() => serializer.serialize_u8(code),
+            _ => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Chord {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChordVisitor;
+
+        impl<'de> Visitor<'de> for ChordVisitor {
+            type Value = Chord;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a keycode or a key chord such as \"Ctrl+Shift+h\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Chord, E> {
+                let code = Keycode::try_from(v)
+                    .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))?;
+                Ok(Chord { modifiers: vec![], key: Key::Code(code) })
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Chord, E> {
+                let code = Keycode::try_from(v)
+                    .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))?;
+                Ok(Chord { modifiers: vec![], key: Key::Code(code) })
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Chord, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(ChordVisitor)
+    }
+}
+
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Keybind {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub modifiers: Vec<Mod>,
-    pub key: u8,
+    pub key: Chord,
     pub action: Action,
 }
 
 impl Keybind {
-    pub fn mod_mask(&self) -> ModMask {
-        let a = self.modifiers.iter().map(|m| u16::from(m.clone()).into());
-        a.reduce(|acc, x| acc | x).unwrap_or(ModMask::ANY)
+    /// All modifiers of the keybind, whether listed separately or as part of the chord.
+    pub fn modifiers(&self) -> impl Iterator<Item = Mod> + '_ {
+        self.modifiers.iter().chain(&self.key.modifiers).copied()
     }
 
-    pub fn key_but_mask(&self) -> KeyButMask {
-        let a = self.modifiers.iter().map(|m| u16::from(m.clone()).into());
-        a.reduce(|acc, x| acc | x).unwrap_or(KeyButMask::from(0u16))
+    /// Resolve the keybind against the current keyboard mapping.
+    ///
+    /// Returns every (modifier mask, keycode) pair that triggers it, or why there are none.
+    pub fn resolve(&self, mapping: &KeyboardMapping) -> Result<Vec<(u16, Keycode)>, String> {
+        let mask = modifier_mask(self.modifiers(), mapping)?;
+
+        match self.key.key {
+            Key::Code(code) => Ok(vec![(mask, code)]),
+            Key::Sym(sym) => {
+                let found = mapping.find(sym);
+                if found.is_empty() {
+                    return Err(format!("no key produces {}", keysym_name(sym)));
+                }
+                Ok(found.into_iter()
+                    .map(|(code, shift)| {
+                        let shift = if shift { u16::from(ModMask::SHIFT) } else { 0 };
+                        (mask | shift, code)
+                    })
+                    .collect())
+            },
+        }
+    }
+
+    /// The (modifier mask, keycode) pairs to grab, including every combination of lock modifiers.
+    pub fn grabs(&self, mapping: &KeyboardMapping) -> Result<Vec<(u16, Keycode)>, String> {
+        let mut grabs = vec![];
+        for (mask, keycode) in self.resolve(mapping)? {
+            grabs.extend(grab_masks(mask, mapping).into_iter().map(|mask| (mask, keycode)));
+        }
+        Ok(grabs)
+    }
+
+    pub fn matches(&self, state: u16, keycode: Keycode, mapping: &KeyboardMapping) -> bool {
+        self.resolve(mapping)
+            .unwrap_or_default()
+            .into_iter()
+            .any(|(mask, code)| code == keycode && modifiers_match(mask, state, mapping))
+    }
+}
+
+
+/// The part of the container a mousebind applies to.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// A tab in the bar; the action applies to that tab
+    Tab,
+    /// The part of the bar without tabs
+    Bar,
+    /// The focused client below the bar; the button is grabbed, so it should use modifiers
+    Client,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Mousebind {
+    /// The button number, or 0 for any button
+    pub button: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Mod>,
+    pub region: Region,
+    pub action: Action,
+}
+
+impl Mousebind {
+    pub fn mask(&self, mapping: &KeyboardMapping) -> Result<u16, String> {
+        modifier_mask(self.modifiers.iter().copied(), mapping)
+    }
+
+    pub fn matches(&self, button: u8, state: u16, region: Region, mapping: &KeyboardMapping) -> bool {
+        (self.button == 0 || self.button == button)
+            && self.region == region
+            && self.mask(mapping).is_ok_and(|mask| modifiers_match(mask, state, mapping))
+    }
+}
+
+
+/// A regular expression matched against window properties, e.g. in [`Rule`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
     }
+
+    /// Whether `text` matches an optional pattern; unset patterns match anything.
+    pub fn matches(pattern: &Option<Pattern>, text: &str) -> bool {
+        pattern.as_ref().is_none_or(|pattern| pattern.is_match(text))
+    }
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = regex::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Regex::new(&value).map(Pattern)
+    }
+}
+
+impl From<Pattern> for String {
+    fn from(value: Pattern) -> String {
+        value.0.as_str().to_owned()
+    }
+}
+
+/// An RGB color, written as `#rrggbb`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub f64, pub f64, pub f64);
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        // from_str_radix alone would take a sign, like in #+1+2+3
+        let channel = |i: usize| value.get(i..i + 2)
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .map(|c| c as f64 / 255.);
+
+        match (value.len(), value.starts_with('#'), channel(1), channel(3), channel(5)) {
+            (7, true, Some(r), Some(g), Some(b)) => Ok(Color(r, g, b)),
+            _ => Err(format!("invalid color '{}', expected #rrggbb", value)),
+        }
+    }
+}
+
+impl From<Color> for String {
+    fn from(Color(r, g, b): Color) -> String {
+        let channel = |c: f64| (c * 255.).round() as u8;
+        format!("#{:02x}{:02x}{:02x}", channel(r), channel(g), channel(b))
+    }
+}
+
+/// Where a newly attached tab is inserted.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    First,
+    Last,
+    /// Right after the focused tab
+    Next,
+    Index(usize),
+}
+
+impl Position {
+    /// Where to insert a tab among `len` tabs, with the tab at `focused` focused.
+    pub fn index(self, len: usize, focused: Option<usize>) -> usize {
+        match self {
+            Position::First => 0,
+            Position::Last => len,
+            Position::Next => focused.map_or(len, |i| (i + 1).min(len)),
+            Position::Index(i) => i.min(len),
+        }
+    }
+}
+
+/// Properties for the clients whose WM_CLASS instance and class and title match.
+///
+/// Unset patterns match anything. When several rules match, the later ones take precedence for
+/// the properties they set.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<Pattern>,
+
+    /// Whether to attach the window at all; refused windows are put back on the root window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub adopt: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    /// Fixed tab color instead of one derived from the window id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    /// Tab label instead of the window title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    /// Pinned tabs are always kept before the others
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned: Option<bool>,
+    /// Whether the window is focused when it is attached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub focus: Option<bool>,
+}
+
+impl Rule {
+    pub fn matches(&self, instance: &str, class: &str, title: &str) -> bool {
+        Pattern::matches(&self.instance, instance)
+            && Pattern::matches(&self.class, class)
+            && Pattern::matches(&self.title, title)
+    }
+}
+
+/// The result of applying every matching [`Rule`] to a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowRules {
+    pub adopt: bool,
+    pub position: Position,
+    pub color: Option<Color>,
+    pub label: Option<String>,
+    pub pinned: bool,
+    pub focus: bool,
+}
+
+impl Default for WindowRules {
+    fn default() -> Self {
+        WindowRules {
+            adopt: true,
+            position: Position::Last,
+            color: None,
+            label: None,
+            pinned: false,
+            focus: true,
+        }
+    }
+}
+
+
+/// A named set of keybinds that is only active after entering the mode, e.g. with a prefix key.
+///
+/// While a mode is active the whole keyboard is grabbed, so its keybinds don't need modifiers.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Mode {
+    pub keybinds: Vec<Keybind>,
+    /// Leave the mode after this many milliseconds without a key press
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// Stay in the mode after running an action, until Escape or the timeout
+    #[serde(default)]
+    pub sticky: bool,
 }
 
 
+/// The configuration, with any fields missing from a file taken from [`DEFAULT_CONFIG`].
 #[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields, default)]
 pub struct Config {
+    /// Other files to read before this one; only meaningful within a file
+    #[serde(skip_serializing)]
+    pub include: Vec<PathBuf>,
     pub keybinds: Vec<Keybind>,
+    pub modes: BTreeMap<String, Mode>,
+    pub mousebinds: Vec<Mousebind>,
+    pub rules: Vec<Rule>,
     pub auto_attach: bool,
     pub colors: bool,
     pub font: Option<String>,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        DEFAULT_CONFIG.to_owned()
+    }
+}
+
+impl Config {
+    /// Apply the rules to a window with the given WM_CLASS and title.
+    pub fn window_rules(&self, instance: &str, class: &str, title: &str) -> WindowRules {
+        let mut result = WindowRules::default();
+
+        for rule in self.rules.iter().filter(|rule| rule.matches(instance, class, title)) {
+            result.adopt = rule.adopt.unwrap_or(result.adopt);
+            result.position = rule.position.unwrap_or(result.position);
+            result.color = rule.color.or(result.color);
+            result.label = rule.label.clone().or(result.label);
+            result.pinned = rule.pinned.unwrap_or(result.pinned);
+            result.focus = rule.focus.unwrap_or(result.focus);
+        }
+        result
+    }
+}
+
 
 #[derive(Debug,)]
 pub enum ConfigError {
     /// Failed to open/read a specified file
     IoError(OsString, std::io::Error),
     /// Failed to parse the configuration
-    FigmentError(Option<OsString>, figment::Error),
+    FigmentError(Option<OsString>, Box<figment::Error>),
+    /// A file includes itself, directly or indirectly
+    IncludeCycle(OsString),
 }
 
 impl std::fmt::Display for ConfigError {
@@ -125,11 +619,14 @@ impl std::fmt::Display for ConfigError {
                 write!(f, "{}: {}", path.to_str().unwrap_or("unknown file"), e)
             },
             ConfigError::FigmentError(None, e) => {
-                write!(f, "no file: {}", e)
+                write!(f, "{}", e)
             },
             ConfigError::FigmentError(Some(path), e) => {
                 write!(f, "{}: {}", path.to_str().unwrap_or("unknown file"), e.kind)
             },
+            ConfigError::IncludeCycle(path) => {
+                write!(f, "{}: include cycle", path.to_str().unwrap_or("unknown file"))
+            },
         }
     }
 }
@@ -137,52 +634,540 @@ impl std::fmt::Display for ConfigError {
 impl std::error::Error for ConfigError {}
 
 
-/// Return the first found configuration path, if any.
+const CONFIG_FILE_NAME: &str = "rstab.toml";
+
+/// Return the configuration files to use, from lowest to highest precedence.
+///
+/// The search order is:
+///     1. `rstab.toml` in each of the `$XDG_CONFIG_DIRS` (`/etc/xdg` by default) that has one,
+///        with earlier directories taking precedence over later ones
+///     2. `cli_path` if provided, otherwise the `RSTAB_CONFIG_PATH` environment variable if set,
+///        otherwise `$XDG_CONFIG_HOME/rstab.toml` (`$HOME/.config/rstab.toml` by default) if it
+///        exists
+///
+fn find_config(cli_path: &Option<PathBuf>) -> Vec<PathBuf> {
+    let system_dirs = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".into());
+
+    let mut paths: Vec<PathBuf> = env::split_paths(&system_dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .filter(|path| path.exists())
+        .collect();
+    paths.reverse();
+
+    let user_path = cli_path.clone()
+        .or_else(|| env::var_os("RSTAB_CONFIG_PATH").map(PathBuf::from))
+        .or_else(|| user_config_dir()
+            .map(|dir| dir.join(CONFIG_FILE_NAME))
+            .filter(|path| path.exists()));
+
+    paths.extend(user_path);
+    paths
+}
+
+/// The user's configuration directory: `$XDG_CONFIG_HOME`, or `$HOME/.config` by default.
+pub fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".config")))
+}
+
+/// Just the `include` directive of a configuration file.
+#[derive(Debug, Default, Deserialize)]
+struct Includes {
+    #[serde(default)]
+    include: Vec<PathBuf>,
+}
+
+/// Return every configuration file to read, in the order they are merged.
 ///
-/// The default search order is:
-///     1. `RSTAB_CONFIG_PATH` environment variable if set
-///     2. `$HOME/.config/rstab.toml` file if it exists
+/// Files listed in a file's `include` directive come right before it, so it can override them.
+/// Relative includes are resolved against the directory of the including file.
+fn config_layers(cli_path: &Option<PathBuf>) -> Result<Vec<PathBuf>, ConfigError> {
+    let mut layers = vec![];
+    for path in find_config(cli_path) {
+        add_layer(&path, &mut layers, &mut vec![])?;
+    }
+    Ok(layers)
+}
+
+fn add_layer(path: &Path, layers: &mut Vec<PathBuf>, stack: &mut Vec<PathBuf>)
+-> Result<(), ConfigError> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+    if stack.contains(&canonical) {
+        return Err(ConfigError::IncludeCycle(path.into()));
+    }
+    if layers.iter().any(|layer| layer.canonicalize().ok().as_ref() == Some(&canonical)) {
+        return Ok(());
+    }
+
+    let data = match read_to_string(path) {
+        Ok(data) => data,
+        Err(e) => return Err(ConfigError::IoError(path.into(), e)),
+    };
+    // Syntax errors are reported when the file is actually parsed
+    let includes: Includes = toml::from_str(&data).unwrap_or_default();
+
+    stack.push(canonical);
+    for include in includes.include {
+        let include = match (include.strip_prefix("~"), env::var_os("HOME")) {
+            (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+            _ => path.parent().unwrap_or(Path::new("")).join(include),
+        };
+        add_layer(&include, layers, stack)?;
+    }
+    stack.pop();
+
+    layers.push(path.to_owned());
+    Ok(())
+}
+
+/// A single setting from the command line, written as `key=value`.
 ///
-fn find_config() -> Option<OsString> {
-    match env::var_os("RSTAB_CONFIG_PATH") {
-        Some(env_path) => return Some(env_path),
-        None => (),
+/// Nested keys are separated by dots, e.g. `modes.resize.timeout=500`. The value is parsed like
+/// an environment variable: booleans, numbers, arrays and dictionaries are recognized, and
+/// anything else is a string.
+#[derive(Debug, Clone)]
+pub struct Override {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Override {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => Ok(Override {
+                key: key.trim().to_owned(),
+                value: value.to_owned(),
+            }),
+            _ => Err(format!("expected key=value, found '{}'", s)),
+        }
     }
+}
 
-    let default_config_path = env::var_os("HOME")
-        .map(|h| Path::new(&h).join(".config/rstab.toml"));
+impl Provider for Override {
+    fn metadata(&self) -> Metadata {
+        Metadata::named(format!("--set {}", self.key))
+    }
 
-    match default_config_path {
-        Some(path) => if path.exists() { return Some(path.into()); }
-        None => ()
+    fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+        let value: Value = self.value.parse().unwrap_or_else(|e| match e {});
+        Serialized::default(&self.key, value).data()
     }
+}
+
+/// The keys of [`Config`] that can be set through `RSTAB_*` environment variables.
+const ENV_KEYS: &[&str] = &[
+    "keybinds", "modes", "mousebinds", "rules", "auto_attach", "colors", "font",
+];
 
-    None
+/// The `RSTAB_*` environment variables as a provider.
+///
+/// Variables that don't name a key of [`Config`] are ignored with a warning, since the environment
+/// is shared with everything else and a stray one shouldn't stop the container from starting.
+fn env_provider() -> Env {
+    Env::prefixed("RSTAB_")
+        .ignore(&["CONFIG_PATH"])
+        .filter(|key| {
+            let field = key.as_str().split("__").next().unwrap_or_default();
+            let known = ENV_KEYS.iter().any(|known| field.eq_ignore_ascii_case(known));
+            if !known {
+                eprintln!("ignoring RSTAB_{}, {} is not a configuration key", key, field);
+            }
+            known
+        })
+        .split("__")
 }
 
 /// Find, read, and parse the configuration.
 ///
-/// If provided, the `cli_path` is used instead of searching for it.
-pub fn read_config(cli_path: &Option<PathBuf>) -> Result<Config, ConfigError> {
+/// The built-in defaults are merged with, in order:
+///     1. every file from [`find_config`] and their includes
+///     2. `RSTAB_*` environment variables, e.g. `RSTAB_AUTO_ATTACH=true`, with `__` separating
+///        nested keys; variables for unknown keys are ignored
+///     3. the `overrides` from the command line
+///
+/// If provided, the `cli_path` is used instead of searching for the per-user file.
+pub fn read_config(cli_path: &Option<PathBuf>, overrides: &[Override])
+-> Result<Config, ConfigError> {
     let mut configment = Figment::from(Serialized::defaults(DEFAULT_CONFIG.to_owned()));
 
-    let config_path = match cli_path {
-        Some(path) => Some(path.into()),
-        None => find_config()
-    };
+    for path in config_layers(cli_path)? {
+        configment = configment.merge(Toml::file_exact(path));
+    }
 
-    if let Some(path) = &config_path {
-        let data = match read_to_string(path) {
-            Ok(data) => data,
-            Err(e) => return Err(ConfigError::IoError(path.into(), e)),
-        };
-        configment = configment.merge(Toml::string(&data));
+    configment = configment.merge(env_provider());
+    for set in overrides {
+        configment = configment.merge(set.clone());
     }
 
     match configment.extract() {
         Ok(config) => Ok(config),
-        Err(e) => Err(ConfigError::FigmentError(config_path, e))
+        Err(e) => {
+            let path = match e.metadata.as_ref().and_then(|m| m.source.as_ref()) {
+                Some(Source::File(path)) => Some(path.into()),
+                _ => None,
+            };
+            Err(ConfigError::FigmentError(path, Box::new(e)))
+        },
     }
 }
 
 
+
+
+#[cfg(test)]
+// figment's Jail takes closures returning its own error type, which clippy finds too large
+#[allow(clippy::result_large_err)]
+mod tests {
+    use super::*;
+    use crate::keysym::tests::mapping;
+    use figment::Jail;
+
+    fn chord(s: &str) -> Chord {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn chord_modifiers_and_key() {
+        let parsed = chord("Ctrl+Shift+h");
+        assert_eq!(parsed.modifiers, vec![Mod::CONTROL, Mod::SHIFT]);
+        assert_eq!(parsed.key, Key::Sym('h' as Keysym));
+
+        assert_eq!(chord("Return"), Chord { modifiers: vec![], key: Key::Sym(0xff0d) });
+    }
+
+    #[test]
+    fn chord_with_plus_key() {
+        let plus = Key::Sym('+' as Keysym);
+        assert_eq!(chord("Ctrl++"), Chord { modifiers: vec![Mod::CONTROL], key: plus });
+        assert_eq!(chord("+"), Chord { modifiers: vec![], key: plus });
+    }
+
+    #[test]
+    fn chord_errors() {
+        assert!("Ctrl+NoSuchKey".parse::<Chord>().is_err());
+        assert!("Meta+h".parse::<Chord>().is_err());
+    }
+
+    #[test]
+    fn chord_display_round_trips() {
+        assert_eq!(chord("ctrl+shift+return").to_string(), "Ctrl+Shift+Return");
+        assert_eq!(chord("Ctrl++").to_string(), "Ctrl+plus");
+        for s in ["Ctrl+Shift+h", "Ctrl++", "Super+BackSpace", "Alt+ä", "0xff0d"] {
+            assert_eq!(chord(&chord(s).to_string()), chord(s), "{}", s);
+        }
+    }
+
+    const NUM_LOCK: u16 = 1 << 4;
+    const CAPS_LOCK: u16 = 1 << 1;
+    const CONTROL: u16 = 1 << 2;
+
+    #[test]
+    fn virtual_modifiers() {
+        let mapping = mapping();
+        assert_eq!(Mod::ALT.mask(&mapping), Some(1 << 3));
+        assert_eq!(Mod::SUPER.mask(&mapping), Some(1 << 6));
+        assert_eq!(Mod::HYPER.mask(&mapping), None);
+        assert!(modifier_mask([Mod::CONTROL, Mod::HYPER], &mapping).is_err());
+    }
+
+    #[test]
+    fn binds_ignore_lock_modifiers() {
+        let mapping = mapping();
+        assert!(modifiers_match(CONTROL, CONTROL, &mapping));
+        assert!(modifiers_match(CONTROL, CONTROL | NUM_LOCK, &mapping));
+        assert!(modifiers_match(CONTROL, CONTROL | CAPS_LOCK | NUM_LOCK, &mapping));
+        // Pointer buttons held down don't count either
+        assert!(modifiers_match(CONTROL, CONTROL | 1 << 8, &mapping));
+
+        assert!(!modifiers_match(CONTROL, CONTROL | u16::from(ModMask::SHIFT), &mapping));
+        assert!(!modifiers_match(CONTROL, 0, &mapping));
+        assert!(modifiers_match(ANY_MASK, u16::from(ModMask::SHIFT), &mapping));
+    }
+
+    #[test]
+    fn binds_asking_for_a_lock_require_it() {
+        let mapping = mapping();
+        assert!(modifiers_match(CONTROL | NUM_LOCK, CONTROL | NUM_LOCK, &mapping));
+        assert!(!modifiers_match(CONTROL | NUM_LOCK, CONTROL, &mapping));
+    }
+
+    #[test]
+    fn grabs_cover_lock_combinations() {
+        let mapping = mapping();
+        let mut masks = grab_masks(CONTROL, &mapping);
+        masks.sort();
+        let locked = [CONTROL, CONTROL | CAPS_LOCK, CONTROL | NUM_LOCK, CONTROL | CAPS_LOCK | NUM_LOCK];
+        assert_eq!(masks, locked);
+
+        let masks = grab_masks(CONTROL | NUM_LOCK, &mapping);
+        assert_eq!(masks, vec![CONTROL | NUM_LOCK, CONTROL | NUM_LOCK | CAPS_LOCK]);
+        assert_eq!(grab_masks(ANY_MASK, &mapping), vec![ANY_MASK]);
+    }
+
+    #[test]
+    fn shifted_keysyms_add_shift() {
+        let mapping = mapping();
+        let keybind = |key: &str| Keybind {
+            modifiers: vec![],
+            key: chord(key),
+            action: Action::Close,
+        };
+        let (shift, control) = (u16::from(ModMask::SHIFT), CONTROL);
+
+        assert_eq!(keybind("Ctrl+h").resolve(&mapping), Ok(vec![(control, 8)]));
+        assert_eq!(keybind("Ctrl+H").resolve(&mapping), Ok(vec![(control | shift, 8)]));
+        let plus = keybind("Ctrl+plus").resolve(&mapping);
+        assert_eq!(plus, Ok(vec![(control, 9), (control | shift, 10)]));
+        assert!(keybind("Escape").resolve(&mapping).is_err());
+    }
+
+    fn rules(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn later_rules_override_earlier_ones_field_by_field() {
+        let config = rules(r##"
+            [[rules]]
+            class = "^Firefox$"
+            label = "web"
+            color = "#ff0000"
+            position = "First"
+
+            [[rules]]
+            title = "Private"
+            label = "private"
+            pinned = true
+        "##);
+
+        let window = config.window_rules("Navigator", "Firefox", "Private Browsing");
+        assert_eq!(window.label.as_deref(), Some("private"));
+        assert_eq!(window.color, Some(Color(1., 0., 0.)));
+        assert_eq!(window.position, Position::First);
+        assert!(window.pinned);
+        assert!(window.adopt && window.focus);
+
+        let window = config.window_rules("Navigator", "Firefox", "Mozilla Firefox");
+        assert_eq!(window.label.as_deref(), Some("web"));
+        assert!(!window.pinned);
+
+        assert_eq!(config.window_rules("st", "St", "Private"), WindowRules {
+            label: Some("private".to_owned()),
+            pinned: true,
+            ..WindowRules::default()
+        });
+    }
+
+    #[test]
+    fn rule_patterns_match_their_own_property() {
+        let config = rules(r#"
+            [[rules]]
+            instance = "^term$"
+            class = "^St$"
+            title = "vim"
+            adopt = false
+        "#);
+
+        assert!(!config.window_rules("term", "St", "vim notes").adopt);
+        // Each pattern only looks at its own property
+        assert!(config.window_rules("St", "term", "vim notes").adopt);
+        assert!(config.window_rules("term", "St", "shell").adopt);
+        assert!(config.window_rules("other", "St", "vim").adopt);
+        assert!(config.window_rules("term", "Xterm", "vim").adopt);
+    }
+
+    #[test]
+    fn colors_are_hex_triplets() {
+        let color = |s: &str| Color::try_from(s.to_owned());
+
+        assert_eq!(color("#ff8000"), Ok(Color(1., 128. / 255., 0.)));
+        assert_eq!(color("#FF8000"), Ok(Color(1., 128. / 255., 0.)));
+        assert_eq!(String::from(color("#ff8000").unwrap()), "#ff8000");
+
+        for bad in ["ff8000", "#f80", "#ff800", "#ff80000", "#gg8000", "#+1+2+3", "", "#ff80é"] {
+            assert!(color(bad).is_err(), "{} was accepted", bad);
+        }
+    }
+
+    #[test]
+    fn positions_insert_within_bounds() {
+        assert_eq!(Position::First.index(3, Some(1)), 0);
+        assert_eq!(Position::Last.index(3, Some(1)), 3);
+        assert_eq!(Position::Next.index(3, Some(1)), 2);
+        assert_eq!(Position::Next.index(3, None), 3);
+        assert_eq!(Position::Index(1).index(3, None), 1);
+        assert_eq!(Position::Index(7).index(3, None), 3);
+        assert_eq!(Position::First.index(0, None), 0);
+        assert_eq!(Position::Next.index(0, None), 0);
+    }
+
+    #[test]
+    fn includes_come_before_the_including_file() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            let dir = jail.directory().to_owned();
+            jail.create_file("system/rstab.toml", "")?;
+            jail.create_file("user/rstab.toml", r#"include = ["a.toml", "b.toml"]"#)?;
+            jail.create_file("user/a.toml", r#"include = ["c.toml"]"#)?;
+            jail.create_file("user/b.toml", r#"include = ["c.toml"]"#)?;
+            jail.create_file("user/c.toml", "")?;
+
+            let layers = config_layers(&None).unwrap();
+            let expected = [
+                "system/rstab.toml", "user/c.toml", "user/a.toml", "user/b.toml", "user/rstab.toml",
+            ].map(|path| dir.join(path));
+            assert_eq!(layers, expected);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            let dir = jail.directory().to_owned();
+            jail.create_dir("user/sub")?;
+            jail.create_file("user/rstab.toml", r#"include = ["sub/x.toml", "~/home.toml"]"#)?;
+            jail.create_file("user/sub/x.toml", r#"include = ["y.toml"]"#)?;
+            jail.create_file("user/sub/y.toml", "")?;
+            jail.create_file("home.toml", "")?;
+
+            let layers = config_layers(&None).unwrap();
+            let expected = ["user/sub/y.toml", "user/sub/x.toml", "home.toml", "user/rstab.toml"]
+                .map(|path| dir.join(path));
+            assert_eq!(layers, expected);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            jail.create_file("user/rstab.toml", r#"include = ["a.toml"]"#)?;
+            jail.create_file("user/a.toml", r#"include = ["b.toml"]"#)?;
+            jail.create_file("user/b.toml", r#"include = ["a.toml"]"#)?;
+
+            let e = config_layers(&None).unwrap_err();
+            assert!(matches!(e, ConfigError::IncludeCycle(_)), "{}", e);
+
+            jail.create_file("user/rstab.toml", r#"include = ["rstab.toml"]"#)?;
+            assert!(matches!(config_layers(&None), Err(ConfigError::IncludeCycle(_))));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn missing_includes_are_errors() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            jail.create_file("user/rstab.toml", r#"include = ["missing.toml"]"#)?;
+            assert!(matches!(config_layers(&None), Err(ConfigError::IoError(..))));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn button_zero_matches_any_button() {
+        let mapping = mapping();
+        let select = &DEFAULT_CONFIG.mousebinds[0];
+        for button in 1..=5 {
+            assert!(select.matches(button, 0, Region::Tab, &mapping));
+        }
+        assert!(!select.matches(1, 0, Region::Bar, &mapping));
+
+        let middle = Mousebind { button: 2, ..select.clone() };
+        assert!(middle.matches(2, 0, Region::Tab, &mapping));
+        assert!(!middle.matches(1, 0, Region::Tab, &mapping));
+    }
+
+    #[test]
+    fn overrides_split_at_the_first_equals_sign() {
+        let set: Override = "modes.resize.timeout=500".parse().unwrap();
+        assert_eq!((set.key.as_str(), set.value.as_str()), ("modes.resize.timeout", "500"));
+
+        let set: Override = " font =Sans=10".parse().unwrap();
+        assert_eq!((set.key.as_str(), set.value.as_str()), ("font", "Sans=10"));
+
+        let set: Override = "font=".parse().unwrap();
+        assert_eq!(set.value, "");
+
+        assert!("auto_attach".parse::<Override>().is_err());
+        assert!("=true".parse::<Override>().is_err());
+        assert!(" =true".parse::<Override>().is_err());
+    }
+
+    #[test]
+    fn override_values_are_typed() {
+        let config: Config = Figment::from(Serialized::defaults(DEFAULT_CONFIG.to_owned()))
+            .merge("auto_attach=true".parse::<Override>().unwrap())
+            .merge("modes.resize.timeout=500".parse::<Override>().unwrap())
+            .merge("modes.resize.keybinds=[]".parse::<Override>().unwrap())
+            .extract()
+            .unwrap();
+        assert!(config.auto_attach);
+        assert_eq!(config.modes["resize"].timeout, Some(500));
+    }
+
+    /// Point the configuration search at empty directories inside the jail.
+    fn isolate(jail: &mut Jail) -> figment::error::Result<()> {
+        jail.clear_env();
+        let dir = jail.directory().to_owned();
+        jail.create_dir("system")?;
+        jail.create_dir("user")?;
+        jail.set_env("XDG_CONFIG_DIRS", dir.join("system").display());
+        jail.set_env("XDG_CONFIG_HOME", dir.join("user").display());
+        jail.set_env("HOME", dir.display());
+        Ok(())
+    }
+
+    #[test]
+    fn files_then_environment_then_overrides() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            let file = "auto_attach = true\ncolors = true\nfont = \"file\"\n";
+            jail.create_file("user/rstab.toml", file)?;
+            jail.set_env("RSTAB_COLORS", "false");
+            jail.set_env("RSTAB_FONT", "env");
+
+            let config = read_config(&None, &[]).unwrap();
+            assert!(config.auto_attach);
+            assert!(!config.colors);
+            assert_eq!(config.font.as_deref(), Some("env"));
+
+            let config = read_config(&None, &["font=set".parse().unwrap()]).unwrap();
+            assert_eq!(config.font.as_deref(), Some("set"));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn unknown_environment_variables_are_ignored() {
+        Jail::expect_with(|jail| {
+            isolate(jail)?;
+            jail.set_env("RSTAB_BAR_HEIGHT", "3");
+            jail.set_env("RSTAB_MODES__RESIZE__TIMEOUT", "500");
+            jail.set_env("RSTAB_MODES__RESIZE__KEYBINDS", "[]");
+
+            let config = read_config(&None, &[]).unwrap();
+            assert_eq!(config.modes["resize"].timeout, Some(500));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn environment_keys_cover_the_config() {
+        let config = Config { font: Some("Sans".to_owned()), ..DEFAULT_CONFIG.to_owned() };
+        let value = toml::Value::try_from(&config).unwrap();
+        for key in value.as_table().unwrap().keys() {
+            assert!(ENV_KEYS.contains(&key.as_str()), "{} is missing from ENV_KEYS", key);
+        }
+    }
+}
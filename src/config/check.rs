@@ -0,0 +1,304 @@
+use toml::Spanned;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use super::*;
+
+
+/// A problem found while checking the configuration.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}:{}: {}", file.display(), line, self.message),
+            (Some(file), None) => write!(f, "{}: {}", file.display(), self.message),
+            (None, _) => write!(f, "{}", self.message),
+        }
+    }
+}
+
+
+/// A single configuration file, which only has to be complete once merged with the others.
+///
+/// This mirrors [`Config`] and [`Mode`] with every field optional, and keeps the positions of the
+/// binds to point diagnostics at them.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+// Most fields are only deserialized to check them
+#[allow(dead_code)]
+struct Layer {
+    include: Option<Vec<PathBuf>>,
+    keybinds: Option<Vec<Spanned<Keybind>>>,
+    modes: BTreeMap<String, ModeLayer>,
+    mousebinds: Option<Vec<Spanned<Mousebind>>>,
+    rules: Option<Vec<Rule>>,
+    auto_attach: Option<bool>,
+    colors: Option<bool>,
+    font: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+// Most fields are only deserialized to check them
+#[allow(dead_code)]
+struct ModeLayer {
+    keybinds: Option<Vec<Spanned<Keybind>>>,
+    timeout: Option<u64>,
+    sticky: Option<bool>,
+}
+
+/// Where a list of binds was defined: the file and the line of each bind.
+///
+/// Lists aren't merged, so this is the last file that sets the list.
+#[derive(Debug, Default, Clone)]
+struct BindSource {
+    file: Option<PathBuf>,
+    lines: Vec<usize>,
+}
+
+impl BindSource {
+    fn new<T>(file: &Path, data: &str, spans: Vec<Spanned<T>>) -> Self {
+        BindSource {
+            file: Some(file.to_owned()),
+            lines: spans.iter().map(|s| line_of(data, s.span().start)).collect(),
+        }
+    }
+
+    fn diagnostic(&self, index: usize, message: String) -> Diagnostic {
+        let message = match self.file {
+            Some(_) => message,
+            None => format!("default config: {}", message),
+        };
+        Diagnostic {
+            file: self.file.clone(),
+            line: self.lines.get(index).copied(),
+            message,
+        }
+    }
+
+    fn describe(&self, index: usize) -> String {
+        match self.lines.get(index) {
+            Some(line) => format!("the bind on line {}", line),
+            None => format!("bind #{}", index + 1),
+        }
+    }
+}
+
+fn line_of(data: &str, offset: usize) -> usize {
+    data[..offset.min(data.len())].matches('\n').count() + 1
+}
+
+
+/// Check the configuration the same way [`read_config`] would find, read and merge it.
+///
+/// Syntax errors and unknown fields or actions are reported for each file, while missing fields
+/// are only reported for the merged result, since another file may provide them. Besides that,
+/// this looks for binds that are duplicated or conflict with each other, and for actions that
+/// enter modes that don't exist.
+/// If a keyboard `mapping` is given, keys and modifiers are also resolved against it.
+pub fn check_config(
+    cli_path: &Option<PathBuf>,
+    overrides: &[Override],
+    mapping: Option<&KeyboardMapping>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    let mut keybinds = BindSource::default();
+    let mut mousebinds = BindSource::default();
+    let mut modes: BTreeMap<String, BindSource> = BTreeMap::new();
+
+    let layers = match config_layers(cli_path) {
+        Ok(layers) => layers,
+        Err(e) => {
+            diagnostics.push(Diagnostic { file: None, line: None, message: e.to_string() });
+            return diagnostics;
+        },
+    };
+
+    for path in layers {
+        let data = match read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                diagnostics.push(Diagnostic { file: Some(path), line: None, message: e.to_string() });
+                continue;
+            },
+        };
+
+        let layer: Layer = match toml::from_str(&data) {
+            Ok(layer) => layer,
+            Err(e) => {
+                diagnostics.push(Diagnostic {
+                    line: e.span().map(|span| line_of(&data, span.start)),
+                    file: Some(path),
+                    message: e.message().to_owned(),
+                });
+                continue;
+            },
+        };
+
+        if let Some(spans) = layer.keybinds {
+            keybinds = BindSource::new(&path, &data, spans);
+        }
+        if let Some(spans) = layer.mousebinds {
+            mousebinds = BindSource::new(&path, &data, spans);
+        }
+        for (name, mode) in layer.modes {
+            if let Some(spans) = mode.keybinds {
+                modes.insert(name, BindSource::new(&path, &data, spans));
+            }
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return diagnostics;
+    }
+
+    let config = match read_config(cli_path, overrides) {
+        Ok(config) => config,
+        Err(e) => {
+            diagnostics.push(Diagnostic { file: None, line: None, message: e.to_string() });
+            return diagnostics;
+        },
+    };
+
+    check_keybinds(&config, &config.keybinds, &keybinds, mapping, &mut diagnostics);
+    for (name, mode) in &config.modes {
+        let source = modes.get(name).cloned().unwrap_or_default();
+        check_keybinds(&config, &mode.keybinds, &source, mapping, &mut diagnostics);
+    }
+    check_mousebinds(&config, &mousebinds, mapping, &mut diagnostics);
+
+    diagnostics
+}
+
+
+fn check_action(config: &Config, action: &Action) -> Option<String> {
+    match action {
+        Action::EnterMode(name) if !config.modes.contains_key(name) => {
+            Some(format!("unknown mode '{}'", name))
+        },
+        _ => None,
+    }
+}
+
+fn same_modifiers(a: &[Mod], b: &[Mod]) -> bool {
+    a.iter().all(|m| b.contains(m)) && b.iter().all(|m| a.contains(m))
+}
+
+/// Report a bind that is triggered the same way as an earlier one.
+fn check_overlap(
+    source: &BindSource,
+    index: usize,
+    earlier: usize,
+    same_action: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let message = if same_action {
+        format!("duplicate of {}", source.describe(earlier))
+    } else {
+        format!("conflicts with {}", source.describe(earlier))
+    };
+    diagnostics.push(source.diagnostic(index, message));
+}
+
+fn check_keybinds(
+    config: &Config,
+    keybinds: &[Keybind],
+    source: &BindSource,
+    mapping: Option<&KeyboardMapping>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let resolved: Vec<_> = keybinds.iter()
+        .map(|keybind| mapping.map(|mapping| keybind.resolve(mapping)))
+        .collect();
+
+    for (i, keybind) in keybinds.iter().enumerate() {
+        if let Some(message) = check_action(config, &keybind.action) {
+            diagnostics.push(source.diagnostic(i, message));
+        }
+
+        if let Some(Err(e)) = &resolved[i] {
+            diagnostics.push(source.diagnostic(i, format!("cannot bind {}: {}", keybind.key, e)));
+            continue;
+        }
+
+        let earlier = (0..i).find(|&j| match (&resolved[i], &resolved[j]) {
+            (Some(Ok(a)), Some(Ok(b))) => a.iter().any(|grab| b.contains(grab)),
+            (None, None) => {
+                let a: Vec<_> = keybind.modifiers().collect();
+                let b: Vec<_> = keybinds[j].modifiers().collect();
+                keybind.key.key == keybinds[j].key.key && same_modifiers(&a, &b)
+            },
+            _ => false,
+        });
+
+        if let Some(j) = earlier {
+            let same_action = keybind.action == keybinds[j].action;
+            check_overlap(source, i, j, same_action, diagnostics);
+        }
+    }
+}
+
+fn check_mousebinds(
+    config: &Config,
+    source: &BindSource,
+    mapping: Option<&KeyboardMapping>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mousebinds = &config.mousebinds;
+
+    for (i, mousebind) in mousebinds.iter().enumerate() {
+        if let Some(message) = check_action(config, &mousebind.action) {
+            diagnostics.push(source.diagnostic(i, message));
+        }
+
+        if let Some(Err(e)) = mapping.map(|mapping| mousebind.mask(mapping)) {
+            let message = format!("cannot bind button {}: {}", mousebind.button, e);
+            diagnostics.push(source.diagnostic(i, message));
+            continue;
+        }
+
+        let earlier = (0..i).find(|&j| {
+            let other = &mousebinds[j];
+            (other.button == 0 || mousebind.button == other.button)
+                && mousebind.region == other.region
+                && same_modifiers(&mousebind.modifiers, &other.modifiers)
+        });
+
+        if let Some(j) = earlier {
+            let same_action = mousebind.action == mousebinds[j].action;
+            check_overlap(source, i, j, same_action, diagnostics);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_may_be_incomplete() {
+        let layer: Layer = toml::from_str("[modes.resize]\ntimeout = 500\n").unwrap();
+        assert!(layer.modes["resize"].keybinds.is_none());
+        assert!(toml::from_str::<Config>("[modes.resize]\ntimeout = 500\n").is_err());
+    }
+
+    #[test]
+    fn layers_reject_unknown_fields() {
+        let data = "[modes.resize]\ntimeout = 500\nbogus = 1\n";
+        let e = toml::from_str::<Layer>(data).unwrap_err();
+        assert_eq!(e.span().map(|span| line_of(data, span.start)), Some(3));
+        assert!(toml::from_str::<Layer>("colours = true\n").is_err());
+        assert!(toml::from_str::<Layer>("keybinds = [{ key = \"h\" }]\n").is_err());
+    }
+}
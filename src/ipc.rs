@@ -0,0 +1,319 @@
+//! The JSON protocol spoken over the Unix socket of each `tabbed-rs` instance.
+//!
+//! Every line sent to the socket is a [`Request`], answered by a single line holding a
+//! [`Response`], e.g. `{"command":"focus","tab":{"index":2}}`. After a `subscribe` request the
+//! connection only carries [`TabEvent`]s, one per line.
+
+use serde::{Deserialize, Serialize};
+
+use x11rb_protocol::parse_display::parse_display;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::config::Action;
+
+
+/// A tab, either by its position in the bar or by its window id.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TabRef {
+    Index(usize),
+    Window(u32),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    List,
+    /// Turn the connection into a stream of events
+    Subscribe,
+    Focus { tab: TabRef },
+    Move { tab: TabRef, to: usize },
+    Swap { a: TabRef, b: TabRef },
+    Detach { tab: TabRef },
+    Close { tab: TabRef },
+    /// Run a config action, with `tab` (or else the focused tab) as its target
+    Action {
+        action: Action,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tab: Option<TabRef>,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TabInfo {
+    pub index: usize,
+    pub window: u32,
+    pub title: String,
+    pub instance: String,
+    pub class: String,
+    pub focused: bool,
+    pub pinned: bool,
+    pub urgent: bool,
+}
+
+/// Something that changed in a container, sent to subscribers.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TabEvent {
+    Attached { window: u32, index: usize },
+    Detached { window: u32 },
+    Focused { window: Option<u32>, index: Option<usize> },
+    Reordered { windows: Vec<u32> },
+    TitleChanged { window: u32, title: String },
+    UrgencyChanged { window: u32, urgent: bool },
+}
+
+/// What a client connection hands to the main loop.
+pub enum Incoming {
+    /// A request and the channel to answer it on
+    Request(Request, mpsc::Sender<Response>),
+    /// A subscription, events should be sent on the channel until it is closed
+    Subscribe(mpsc::Sender<TabEvent>),
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tabs: Option<Vec<TabInfo>>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response { ok: true, ..Default::default() }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Response { ok: false, error: Some(message.into()), ..Default::default() }
+    }
+
+    pub fn tabs(tabs: Vec<TabInfo>) -> Self {
+        Response { ok: true, tabs: Some(tabs), ..Default::default() }
+    }
+}
+
+impl From<Result<(), String>> for Response {
+    fn from(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Response::ok(),
+            Err(e) => Response::error(e),
+        }
+    }
+}
+
+
+/// Where the instance with container window `win_id` listens.
+///
+/// This is under `$XDG_RUNTIME_DIR`, or the temporary directory if that isn't set, in a directory
+/// for the display from `$DISPLAY` since window ids are only unique within one X server. The path
+/// is also published in the `_TABBED_RS_SOCKET` property of the container.
+pub fn socket_path(win_id: u32) -> PathBuf {
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+
+    // Screens share their server and so their window ids, so only the host and display count
+    let display = match parse_display(None) {
+        Some(display) => format!("{}:{}", display.host, display.display),
+        None => "default".to_owned(),
+    };
+
+    dir.join("tabbed-rs").join(display).join(format!("0x{:x}.sock", win_id))
+}
+
+/// Send a single request to the instance listening at `path` and wait for the response.
+pub fn request(path: &Path, request: &Request) -> io::Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+    write_line(&mut stream, request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Bind the socket at `path`, replacing a stale one left behind by a crashed instance.
+pub fn bind(path: &PathBuf) -> io::Result<UnixListener> {
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    }
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// Accept connections on a thread, handing each request to the main loop.
+///
+/// Requests and subscriptions are sent through `sender` after being wrapped by `wrap`.
+pub fn serve<T: Send + 'static>(
+    listener: UnixListener,
+    sender: mpsc::Sender<T>,
+    wrap: fn(Incoming) -> T,
+) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_client(stream, sender, wrap) {
+                    eprintln!("ipc: {}", e);
+                }
+            });
+        }
+    });
+}
+
+fn handle_client<T>(
+    stream: UnixStream,
+    sender: mpsc::Sender<T>,
+    wrap: fn(Incoming) -> T,
+) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str(&line) {
+            Ok(Request::Subscribe) => {
+                write_line(&mut writer, &Response::ok())?;
+                return stream_events(writer, sender, wrap);
+            },
+            Ok(request) => {
+                let (reply, response) = mpsc::channel();
+                if sender.send(wrap(Incoming::Request(request, reply))).is_err() {
+                    break;
+                }
+                response.recv().unwrap_or_else(|_| Response::error("tabbed-rs is exiting"))
+            },
+            Err(e) => Response::error(format!("invalid request: {}", e)),
+        };
+
+        write_line(&mut writer, &response)?;
+    }
+    Ok(())
+}
+
+fn stream_events<T>(
+    mut writer: UnixStream,
+    sender: mpsc::Sender<T>,
+    wrap: fn(Incoming) -> T,
+) -> io::Result<()> {
+    let (events, receiver) = mpsc::channel();
+    if sender.send(wrap(Incoming::Subscribe(events))).is_err() {
+        return Ok(());
+    }
+
+    // Dropping the receiver when the client goes away unsubscribes it.
+    for event in receiver {
+        write_line(&mut writer, &event)?;
+    }
+    Ok(())
+}
+
+fn write_line(writer: &mut UnixStream, value: &impl Serialize) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, value)?;
+    writer.write_all(b"\n")
+}
+
+
+#[cfg(test)]
+// figment's Jail takes closures returning its own error type, which clippy finds too large
+#[allow(clippy::result_large_err)]
+mod tests {
+    use super::*;
+    use figment::Jail;
+    use serde_json::{json, Value};
+
+    /// Deserialize `value`, check that it serializes back to the same JSON and return it.
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: Value) -> T {
+        let parsed: T = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), value);
+        parsed
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            json!({"command": "list"}),
+            json!({"command": "subscribe"}),
+            json!({"command": "focus", "tab": {"index": 2}}),
+            json!({"command": "move", "tab": {"window": 4194306}, "to": 0}),
+            json!({"command": "swap", "a": {"index": 0}, "b": {"window": 7}}),
+            json!({"command": "detach", "tab": {"index": 1}}),
+            json!({"command": "close", "tab": {"window": 7}}),
+            json!({"command": "action", "action": "DetachAll"}),
+            json!({"command": "action", "action": {"Focus": 3}, "tab": {"index": 1}}),
+        ];
+        for request in requests {
+            round_trip::<Request>(request);
+        }
+
+        let request = round_trip(json!({"command": "swap", "a": {"index": 0}, "b": {"window": 7}}));
+        assert!(matches!(request, Request::Swap { a: TabRef::Index(0), b: TabRef::Window(7) }));
+    }
+
+    #[test]
+    fn unknown_requests_are_rejected() {
+        assert!(serde_json::from_str::<Request>(r#"{"command":"explode"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command":"focus"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command":"focus","tab":{"nth":1}}"#).is_err());
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let ok: Response = round_trip(json!({"ok": true}));
+        assert!(ok.ok && ok.error.is_none() && ok.tabs.is_none());
+
+        let error: Response = round_trip(json!({"ok": false, "error": "no tab 3"}));
+        assert_eq!(error.error.as_deref(), Some("no tab 3"));
+
+        let tabs: Response = round_trip(json!({"ok": true, "tabs": [{
+            "index": 0, "window": 7, "title": "vim", "instance": "st", "class": "St",
+            "focused": true, "pinned": false, "urgent": false,
+        }]}));
+        assert_eq!(tabs.tabs.unwrap()[0].title, "vim");
+
+        assert_eq!(serde_json::to_value(Response::ok()).unwrap(), json!({"ok": true}));
+        assert_eq!(
+            serde_json::to_value(Response::from(Err("nope".to_owned()))).unwrap(),
+            json!({"ok": false, "error": "nope"}),
+        );
+    }
+
+    #[test]
+    fn socket_path_per_display() {
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+            jail.set_env("XDG_RUNTIME_DIR", "/run/user/1000");
+
+            jail.set_env("DISPLAY", ":1");
+            assert_eq!(socket_path(0x1a), Path::new("/run/user/1000/tabbed-rs/:1/0x1a.sock"));
+
+            // The screen doesn't count
+            jail.set_env("DISPLAY", "remote:2.1");
+            assert_eq!(socket_path(0x1a), Path::new("/run/user/1000/tabbed-rs/remote:2/0x1a.sock"));
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn socket_path_fallbacks() {
+        Jail::expect_with(|jail| {
+            jail.clear_env();
+
+            let path = socket_path(0x400002);
+            assert_eq!(path, std::env::temp_dir().join("tabbed-rs/default/0x400002.sock"));
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,471 @@
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::ConnectionExt;
+use x11rb_protocol::protocol::xproto::{Keycode, Keysym};
+
+
+/// Keysym names as used by `XStringToKeysym`, see `X11/keysymdef.h`.
+///
+/// Where several names share a keysym, the first one is used when printing.
+const KEYSYM_NAMES: &[(&str, Keysym)] = &[
+    ("space", 0x0020),
+    ("exclam", 0x0021),
+    ("quotedbl", 0x0022),
+    ("numbersign", 0x0023),
+    ("dollar", 0x0024),
+    ("percent", 0x0025),
+    ("ampersand", 0x0026),
+    ("apostrophe", 0x0027),
+    ("parenleft", 0x0028),
+    ("parenright", 0x0029),
+    ("asterisk", 0x002a),
+    ("plus", 0x002b),
+    ("comma", 0x002c),
+    ("minus", 0x002d),
+    ("period", 0x002e),
+    ("slash", 0x002f),
+    ("colon", 0x003a),
+    ("semicolon", 0x003b),
+    ("less", 0x003c),
+    ("equal", 0x003d),
+    ("greater", 0x003e),
+    ("question", 0x003f),
+    ("at", 0x0040),
+    ("bracketleft", 0x005b),
+    ("backslash", 0x005c),
+    ("bracketright", 0x005d),
+    ("asciicircum", 0x005e),
+    ("underscore", 0x005f),
+    ("grave", 0x0060),
+    ("braceleft", 0x007b),
+    ("bar", 0x007c),
+    ("braceright", 0x007d),
+    ("asciitilde", 0x007e),
+
+    ("section", 0x00a7),
+    ("degree", 0x00b0),
+    ("Adiaeresis", 0x00c4),
+    ("Odiaeresis", 0x00d6),
+    ("Udiaeresis", 0x00dc),
+    ("ssharp", 0x00df),
+    ("agrave", 0x00e0),
+    ("adiaeresis", 0x00e4),
+    ("ccedilla", 0x00e7),
+    ("egrave", 0x00e8),
+    ("eacute", 0x00e9),
+    ("odiaeresis", 0x00f6),
+    ("udiaeresis", 0x00fc),
+
+    ("ISO_Level3_Shift", 0xfe03),
+    ("ISO_Left_Tab", 0xfe20),
+
+    ("BackSpace", 0xff08),
+    ("Tab", 0xff09),
+    ("Linefeed", 0xff0a),
+    ("Clear", 0xff0b),
+    ("Return", 0xff0d),
+    ("Pause", 0xff13),
+    ("Scroll_Lock", 0xff14),
+    ("Sys_Req", 0xff15),
+    ("Escape", 0xff1b),
+    ("Home", 0xff50),
+    ("Left", 0xff51),
+    ("Up", 0xff52),
+    ("Right", 0xff53),
+    ("Down", 0xff54),
+    ("Prior", 0xff55),
+    ("Page_Up", 0xff55),
+    ("Next", 0xff56),
+    ("Page_Down", 0xff56),
+    ("End", 0xff57),
+    ("Begin", 0xff58),
+    ("Select", 0xff60),
+    ("Print", 0xff61),
+    ("Execute", 0xff62),
+    ("Insert", 0xff63),
+    ("Menu", 0xff67),
+    ("Find", 0xff68),
+    ("Cancel", 0xff69),
+    ("Help", 0xff6a),
+    ("Break", 0xff6b),
+    ("Mode_switch", 0xff7e),
+    ("Num_Lock", 0xff7f),
+
+    ("KP_Space", 0xff80),
+    ("KP_Tab", 0xff89),
+    ("KP_Enter", 0xff8d),
+    ("KP_Home", 0xff95),
+    ("KP_Left", 0xff96),
+    ("KP_Up", 0xff97),
+    ("KP_Right", 0xff98),
+    ("KP_Down", 0xff99),
+    ("KP_Prior", 0xff9a),
+    ("KP_Page_Up", 0xff9a),
+    ("KP_Next", 0xff9b),
+    ("KP_Page_Down", 0xff9b),
+    ("KP_End", 0xff9c),
+    ("KP_Begin", 0xff9d),
+    ("KP_Insert", 0xff9e),
+    ("KP_Delete", 0xff9f),
+    ("KP_Multiply", 0xffaa),
+    ("KP_Add", 0xffab),
+    ("KP_Separator", 0xffac),
+    ("KP_Subtract", 0xffad),
+    ("KP_Decimal", 0xffae),
+    ("KP_Divide", 0xffaf),
+    ("KP_0", 0xffb0),
+    ("KP_1", 0xffb1),
+    ("KP_2", 0xffb2),
+    ("KP_3", 0xffb3),
+    ("KP_4", 0xffb4),
+    ("KP_5", 0xffb5),
+    ("KP_6", 0xffb6),
+    ("KP_7", 0xffb7),
+    ("KP_8", 0xffb8),
+    ("KP_9", 0xffb9),
+    ("KP_Equal", 0xffbd),
+
+    ("F1", 0xffbe),
+    ("F2", 0xffbf),
+    ("F3", 0xffc0),
+    ("F4", 0xffc1),
+    ("F5", 0xffc2),
+    ("F6", 0xffc3),
+    ("F7", 0xffc4),
+    ("F8", 0xffc5),
+    ("F9", 0xffc6),
+    ("F10", 0xffc7),
+    ("F11", 0xffc8),
+    ("F12", 0xffc9),
+    ("F13", 0xffca),
+    ("F14", 0xffcb),
+    ("F15", 0xffcc),
+    ("F16", 0xffcd),
+    ("F17", 0xffce),
+    ("F18", 0xffcf),
+    ("F19", 0xffd0),
+    ("F20", 0xffd1),
+    ("F21", 0xffd2),
+    ("F22", 0xffd3),
+    ("F23", 0xffd4),
+    ("F24", 0xffd5),
+
+    ("Shift_L", 0xffe1),
+    ("Shift_R", 0xffe2),
+    ("Control_L", 0xffe3),
+    ("Control_R", 0xffe4),
+    ("Caps_Lock", 0xffe5),
+    ("Shift_Lock", 0xffe6),
+    ("Meta_L", 0xffe7),
+    ("Meta_R", 0xffe8),
+    ("Alt_L", 0xffe9),
+    ("Alt_R", 0xffea),
+    ("Super_L", 0xffeb),
+    ("Super_R", 0xffec),
+    ("Hyper_L", 0xffed),
+    ("Hyper_R", 0xffee),
+    ("Delete", 0xffff),
+
+    ("XF86AudioLowerVolume", 0x1008ff11),
+    ("XF86AudioMute", 0x1008ff12),
+    ("XF86AudioRaiseVolume", 0x1008ff13),
+    ("XF86AudioPlay", 0x1008ff14),
+    ("XF86AudioStop", 0x1008ff15),
+    ("XF86AudioPrev", 0x1008ff16),
+    ("XF86AudioNext", 0x1008ff17),
+    ("XF86Back", 0x1008ff26),
+    ("XF86Forward", 0x1008ff27),
+];
+
+pub const XK_SCROLL_LOCK: Keysym = 0xff14;
+pub const XK_ESCAPE: Keysym = 0xff1b;
+pub const XK_NUM_LOCK: Keysym = 0xff7f;
+pub const XK_ALT_L: Keysym = 0xffe9;
+pub const XK_ALT_R: Keysym = 0xffea;
+pub const XK_SUPER_L: Keysym = 0xffeb;
+pub const XK_SUPER_R: Keysym = 0xffec;
+pub const XK_HYPER_L: Keysym = 0xffed;
+pub const XK_HYPER_R: Keysym = 0xffee;
+
+/// Keysyms for unicode characters outside of Latin-1 are offset by this.
+const UNICODE_OFFSET: Keysym = 0x0100_0000;
+
+
+/// Look up a keysym by name.
+///
+/// Accepts the names from `X11/keysymdef.h` (without the `XK_` prefix), single characters such
+/// as `h` or `ä`, and raw values in hex such as `0xff0d`. Multi-character names are matched
+/// case-insensitively if there is no exact match.
+pub fn keysym_from_name(name: &str) -> Option<Keysym> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(char_to_keysym(c));
+    }
+
+    if let Some(&(_, sym)) = KEYSYM_NAMES.iter().find(|(n, _)| *n == name) {
+        return Some(sym);
+    }
+
+    if let Some(hex) = name.strip_prefix("0x") {
+        return Keysym::from_str_radix(hex, 16).ok();
+    }
+
+    KEYSYM_NAMES.iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, sym)| sym)
+}
+
+/// The canonical name of a keysym, suitable for [`keysym_from_name`].
+pub fn keysym_name(sym: Keysym) -> String {
+    if let Some(&(name, _)) = KEYSYM_NAMES.iter().find(|&&(_, s)| s == sym) {
+        return name.to_owned();
+    }
+
+    match keysym_to_char(sym) {
+        Some(c) if !c.is_control() && !c.is_whitespace() => c.to_string(),
+        _ => format!("0x{:x}", sym),
+    }
+}
+
+fn char_to_keysym(c: char) -> Keysym {
+    match c as u32 {
+        code @ (0x20..=0x7e | 0xa0..=0xff) => code,
+        code => code + UNICODE_OFFSET,
+    }
+}
+
+fn keysym_to_char(sym: Keysym) -> Option<char> {
+    match sym {
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(sym),
+        _ if sym > UNICODE_OFFSET => char::from_u32(sym - UNICODE_OFFSET),
+        _ => None,
+    }
+}
+
+/// Whether the keysym belongs to a modifier key such as Shift_L or Num_Lock.
+pub fn is_modifier_keysym(sym: Keysym) -> bool {
+    matches!(sym, 0xfe01..=0xfe13 | 0xff7e | 0xff7f | 0xffe1..=0xffee)
+}
+
+/// The (lowercase, uppercase) pair of a keysym, if it has case.
+fn keysym_case(sym: Keysym) -> (Keysym, Keysym) {
+    match keysym_to_char(sym) {
+        Some(c) => {
+            let lower = single_char(c.to_lowercase()).unwrap_or(c);
+            let upper = single_char(c.to_uppercase()).unwrap_or(c);
+            (char_to_keysym(lower), char_to_keysym(upper))
+        },
+        None => (sym, sym),
+    }
+}
+
+fn single_char(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+
+/// A snapshot of the server's keycode to keysym table and modifier mapping.
+#[derive(Debug, Clone)]
+pub struct KeyboardMapping {
+    min_keycode: Keycode,
+    keysyms_per_keycode: usize,
+    keysyms: Vec<Keysym>,
+    keycodes_per_modifier: usize,
+    modifier_keycodes: Vec<Keycode>,
+}
+
+impl KeyboardMapping {
+    pub fn fetch(conn: &impl Connection) -> Result<Self, ReplyError> {
+        let setup = conn.setup();
+        let count = setup.max_keycode - setup.min_keycode + 1;
+        let keyboard = conn.get_keyboard_mapping(setup.min_keycode, count)?;
+        let modifiers = conn.get_modifier_mapping()?.reply()?;
+        let keyboard = keyboard.reply()?;
+
+        Ok(Self::new(
+            setup.min_keycode,
+            keyboard.keysyms_per_keycode.into(),
+            keyboard.keysyms,
+            modifiers.keycodes_per_modifier().into(),
+            modifiers.keycodes,
+        ))
+    }
+
+    /// Build a mapping from the contents of GetKeyboardMapping and GetModifierMapping replies.
+    pub fn new(
+        min_keycode: Keycode,
+        keysyms_per_keycode: usize,
+        keysyms: Vec<Keysym>,
+        keycodes_per_modifier: usize,
+        modifier_keycodes: Vec<Keycode>,
+    ) -> Self {
+        Self { min_keycode, keysyms_per_keycode, keysyms, keycodes_per_modifier, modifier_keycodes }
+    }
+
+    /// The keysyms of the first and second (shifted) level of a keycode.
+    ///
+    /// As in Xlib, an empty second level with a cased first level means the uppercase keysym.
+    fn levels(&self, keycode: Keycode) -> (Keysym, Keysym) {
+        let start = (keycode - self.min_keycode) as usize * self.keysyms_per_keycode;
+        let syms = &self.keysyms[start..start + self.keysyms_per_keycode];
+
+        let first = syms.first().copied().unwrap_or(0);
+        match syms.get(1).copied().unwrap_or(0) {
+            0 => keysym_case(first),
+            second => (first, second),
+        }
+    }
+
+    fn keycode_count(&self) -> usize {
+        self.keysyms.len() / self.keysyms_per_keycode.max(1)
+    }
+
+    fn keycodes(&self) -> impl Iterator<Item = Keycode> + '_ {
+        (0..self.keycode_count()).map(|i| self.min_keycode + i as Keycode)
+    }
+
+    /// The keysym a keycode produces without any modifiers.
+    pub fn keysym(&self, keycode: Keycode) -> Option<Keysym> {
+        let in_range = (keycode as usize) < self.min_keycode as usize + self.keycode_count();
+        if keycode < self.min_keycode || !in_range {
+            return None;
+        }
+        Some(self.levels(keycode).0).filter(|&sym| sym != 0)
+    }
+
+    /// Find every keycode that produces `sym`.
+    ///
+    /// Each result also says whether Shift needs to be held for it.
+    pub fn find(&self, sym: Keysym) -> Vec<(Keycode, bool)> {
+        self.keycodes()
+            .filter_map(|keycode| match self.levels(keycode) {
+                (first, _) if first == sym => Some((keycode, false)),
+                (_, second) if second == sym => Some((keycode, true)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The modifier mask that one of the given keysyms is bound to, if any.
+    ///
+    /// This is how virtual modifiers like Super or Num Lock are mapped to one of Mod1 to Mod5.
+    pub fn modifier_mask(&self, syms: &[Keysym]) -> Option<u16> {
+        let per_modifier = self.keycodes_per_modifier.max(1);
+
+        self.modifier_keycodes
+            .chunks(per_modifier)
+            .position(|keycodes| keycodes.iter()
+                .filter(|&&keycode| keycode >= self.min_keycode)
+                .any(|&keycode| syms.contains(&self.levels(keycode).0)))
+            .map(|index| 1 << index)
+    }
+
+    /// The modifiers that toggle state rather than being held: Caps Lock, Num Lock, Scroll Lock.
+    pub fn lock_mask(&self) -> u16 {
+        let num_lock = self.modifier_mask(&[XK_NUM_LOCK]).unwrap_or(0);
+        let scroll_lock = self.modifier_mask(&[XK_SCROLL_LOCK]).unwrap_or(0);
+        LOCK_MASK | num_lock | scroll_lock
+    }
+}
+
+const LOCK_MASK: u16 = 1 << 1;
+
+/// Every combination of the modifier bits in `locks`, including none at all.
+pub fn lock_combinations(locks: u16) -> Vec<u16> {
+    let bits: Vec<u16> = (0..8).map(|i| 1 << i).filter(|bit| locks & bit != 0).collect();
+
+    (0..1u32 << bits.len())
+        .map(|set| bits.iter()
+            .enumerate()
+            .filter(|(i, _)| set & (1 << i) != 0)
+            .fold(0, |acc, (_, bit)| acc | bit))
+        .collect()
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A small keyboard: h, plus, equal/plus, 1/exclam, Num_Lock on Mod2, Alt_L on Mod1 and
+    /// Super_L on Mod4.
+    pub(crate) fn mapping() -> KeyboardMapping {
+        let keysyms = vec![
+            'h' as Keysym, 0,
+            '+' as Keysym, 0,
+            '=' as Keysym, '+' as Keysym,
+            '1' as Keysym, '!' as Keysym,
+            XK_NUM_LOCK, 0,
+            XK_ALT_L, 0,
+            XK_SUPER_L, 0,
+        ];
+        // Shift, Lock, Control, Mod1 to Mod5, one keycode each
+        let modifiers = vec![0, 0, 0, 13, 12, 0, 14, 0];
+        KeyboardMapping::new(8, 2, keysyms, 1, modifiers)
+    }
+
+    #[test]
+    fn keysym_names() {
+        assert_eq!(keysym_from_name("h"), Some(0x68));
+        assert_eq!(keysym_from_name("+"), Some(0x2b));
+        assert_eq!(keysym_from_name("ä"), Some(0xe4));
+        assert_eq!(keysym_from_name("€"), Some(0x20ac + UNICODE_OFFSET));
+        assert_eq!(keysym_from_name("Return"), Some(0xff0d));
+        assert_eq!(keysym_from_name("0xff0d"), Some(0xff0d));
+        assert_eq!(keysym_from_name("NoSuchKey"), None);
+    }
+
+    #[test]
+    fn keysym_names_ignore_case_as_a_fallback() {
+        assert_eq!(keysym_from_name("return"), Some(0xff0d));
+        assert_eq!(keysym_from_name("ESCAPE"), Some(XK_ESCAPE));
+        // Single characters keep their case
+        assert_ne!(keysym_from_name("H"), keysym_from_name("h"));
+    }
+
+    #[test]
+    fn keysym_names_round_trip() {
+        for sym in [0x68, 0x2b, 0xe4, 0x20ac + UNICODE_OFFSET, 0xff0d, XK_NUM_LOCK] {
+            assert_eq!(keysym_from_name(&keysym_name(sym)), Some(sym), "{}", keysym_name(sym));
+        }
+        assert_eq!(keysym_name(0xff0d), "Return");
+    }
+
+    #[test]
+    fn find_keycodes() {
+        let mapping = mapping();
+        assert_eq!(mapping.find('h' as Keysym), vec![(8, false)]);
+        // An empty second level means the uppercase keysym
+        assert_eq!(mapping.find('H' as Keysym), vec![(8, true)]);
+        assert_eq!(mapping.find('!' as Keysym), vec![(11, true)]);
+        assert_eq!(mapping.find('+' as Keysym), vec![(9, false), (10, true)]);
+        assert_eq!(mapping.find(XK_ESCAPE), vec![]);
+    }
+
+    #[test]
+    fn modifier_masks() {
+        let mapping = mapping();
+        assert_eq!(mapping.modifier_mask(&[XK_ALT_L, XK_ALT_R]), Some(1 << 3));
+        assert_eq!(mapping.modifier_mask(&[XK_NUM_LOCK]), Some(1 << 4));
+        assert_eq!(mapping.modifier_mask(&[XK_HYPER_L]), None);
+        assert_eq!(mapping.lock_mask(), LOCK_MASK | 1 << 4);
+    }
+
+    #[test]
+    fn lock_combinations_include_none() {
+        assert_eq!(lock_combinations(0), vec![0]);
+        let all = vec![0, LOCK_MASK, 1 << 4, LOCK_MASK | 1 << 4];
+        assert_eq!(lock_combinations(LOCK_MASK | 1 << 4), all);
+    }
+
+    #[test]
+    fn keysym_of_keycode() {
+        let mapping = mapping();
+        assert_eq!(mapping.keysym(8), Some('h' as Keysym));
+        assert_eq!(mapping.keysym(7), None);
+        assert_eq!(mapping.keysym(15), None);
+    }
+}
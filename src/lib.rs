@@ -1,5 +1,7 @@
 
 pub mod config;
+pub mod ipc;
+pub mod keysym;
 pub mod x11;
 
 
@@ -3,11 +3,14 @@ use x11rb::atom_manager;
 use x11rb::connection::Connection;
 //use x11rb::rust_connection::RustConnection;
 use x11rb::errors::ReplyOrIdError;
-use x11rb::errors::ReplyError;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::properties::{WmSizeHints, WmSizeHintsSpecification};
 use x11rb::protocol::xproto::*;
 //use x11rb::protocol::Event;
 use x11rb::wrapper::ConnectionExt as _;
 
+use crate::config::Action;
+
 atom_manager! {
     pub Atoms: AtomsCookie {
         UTF8_STRING,
@@ -15,6 +18,18 @@ atom_manager! {
         WM_PROTOCOLS,
         _NET_WM_NAME,
         WM_NAME,
+        _TABBED_RS_SOCKET,
+        _TABBED_RS_CLIENTS,
+        _TABBED_RS_ACTIVE,
+        _TABBED_RS_COMMAND,
+        _TABBED_RS_ORIGINAL_GEOMETRY,
+        _TABBED_RS_ORIGINAL_HINTS,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_SUPPORTING_WM_CHECK,
+        _NET_WM_DESKTOP,
+        _NET_DESKTOP_NAMES,
+        _NET_WM_PID,
     }
 }
 
@@ -54,12 +69,13 @@ pub fn rs_create_window(
         AtomEnum::STRING,
         class.as_bytes(),
     )?;
+    // WM_CLASS holds the instance and the class, each null-terminated
     conn.change_property8(
         PropMode::REPLACE,
         win_id,
         AtomEnum::WM_CLASS,
         AtomEnum::STRING,
-        class.as_bytes(),
+        format!("{}\0{}\0", class, class).as_bytes(),
     )?;
     conn.change_property8(
         PropMode::REPLACE,
@@ -87,6 +103,17 @@ pub fn rs_get_window_class(conn: &impl Connection, window: Window, )
     Ok(String::from_utf8(bytes).unwrap_or_default())
 }
 
+/// The (instance, class) pair of a window's WM_CLASS.
+pub fn rs_get_wm_class(conn: &impl Connection, window: Window, )
+    -> Result<(String, String), ReplyError> {
+    let bytes = rs_get_window_property8(conn, AtomEnum::WM_CLASS.into(), window)?;
+    let mut parts = bytes.split(|&b| b == 0).map(|part| String::from_utf8_lossy(part).into_owned());
+
+    let instance = parts.next().unwrap_or_default();
+    let class = parts.next().unwrap_or_default();
+    Ok((instance, class))
+}
+
 pub fn rs_get_window_name(conn: &impl Connection, atoms: &Atoms, window: Window, )
     -> Result<String, ReplyError> {
     let mut bytes = rs_get_window_property8(conn, atoms._NET_WM_NAME, window)?;
@@ -98,6 +125,40 @@ pub fn rs_get_window_name(conn: &impl Connection, atoms: &Atoms, window: Window,
     Ok(String::from_utf8(bytes).unwrap_or_default())
 }
 
+/// Whether the urgency hint is set in a window's WM_HINTS.
+pub fn rs_get_urgency(conn: &impl Connection, window: Window) -> Result<bool, ReplyError> {
+    const URGENCY_HINT: u32 = 1 << 8;
+
+    let hints = rs_get_window_property32(conn, AtomEnum::WM_HINTS.into(), window)?;
+    Ok(hints.first().is_some_and(|flags| flags & URGENCY_HINT != 0))
+}
+
+
+/// The tabs of a tabbed-rs container in order, from `_TABBED_RS_CLIENTS`.
+///
+/// This is `None` if the property isn't set, e.g. because the window isn't a tabbed-rs container.
+pub fn rs_get_tabbed_clients(conn: &impl Connection, atoms: &Atoms, window: Window)
+-> Result<Option<Vec<Window>>, ReplyError> {
+    let reply = rs_get_property_any(conn, atoms._TABBED_RS_CLIENTS, window)?;
+    if reply.type_ == u32::from(AtomEnum::NONE) {
+        return Ok(None);
+    }
+    Ok(Some(reply.value32().into_iter().flatten().collect()))
+}
+
+/// The active tab of a tabbed-rs container, from `_TABBED_RS_ACTIVE`.
+///
+/// The outer `None` means the property isn't set, the inner one that there are no tabs.
+pub fn rs_get_tabbed_active(conn: &impl Connection, atoms: &Atoms, window: Window)
+-> Result<Option<Option<Window>>, ReplyError> {
+    let reply = rs_get_property_any(conn, atoms._TABBED_RS_ACTIVE, window)?;
+    if reply.type_ == u32::from(AtomEnum::NONE) {
+        return Ok(None);
+    }
+    let active = reply.value32().into_iter().flatten().next();
+    Ok(Some(active))
+}
+
 
 fn rs_get_property_any(conn: &impl Connection, atom: Atom, window: Window)
 -> Result<GetPropertyReply, ReplyError> {
@@ -142,6 +203,211 @@ pub fn rs_send_delete_window_event(conn: &impl Connection, atoms: &Atoms, window
     conn.send_event(false, window, EventMask::NO_EVENT, event)?.check()
 }
 
+/// Ask the window manager to focus `window` through `_NET_ACTIVE_WINDOW`.
+pub fn rs_request_focus(conn: &impl Connection, atoms: &Atoms, root: Window, window: Window)
+-> Result<(), ReplyError> {
+    // Source indication 2 means a pager, which window managers don't second-guess.
+    let event = ClientMessageEvent::new(32, window, atoms._NET_ACTIVE_WINDOW, [2, 0, 0, 0, 0]);
+    let mask = EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY;
+    conn.send_event(false, root, mask, event)?.check()
+}
+
+
+/// Record where a window is on the screen and its WM_NORMAL_HINTS before a container adopts it,
+/// so [`rs_restore_window`] can put it back there.
+///
+/// The geometry is kept in `_TABBED_RS_ORIGINAL_GEOMETRY` as `[x, y, width, height]` relative to
+/// the root window, with negative positions wrapped around. Nothing is recorded for a window that
+/// already has it, like a tab moving to another container.
+pub fn rs_save_geometry(conn: &impl Connection, atoms: &Atoms, window: Window)
+-> Result<(), ReplyError> {
+    let saved = rs_get_property_any(conn, atoms._TABBED_RS_ORIGINAL_GEOMETRY, window)?;
+    if saved.type_ != u32::from(AtomEnum::NONE) {
+        return Ok(());
+    }
+
+    let geometry = conn.get_geometry(window)?.reply()?;
+    let origin = conn.translate_coordinates(window, geometry.root, 0, 0)?.reply()?;
+    conn.change_property32(
+        PropMode::REPLACE,
+        window,
+        atoms._TABBED_RS_ORIGINAL_GEOMETRY,
+        AtomEnum::CARDINAL,
+        &[origin.dst_x as u32, origin.dst_y as u32, geometry.width.into(), geometry.height.into()],
+    )?;
+
+    rs_save_hints(conn, atoms, window)
+}
+
+/// Record just the WM_NORMAL_HINTS of a window, for windows that are already in a container and
+/// whose position on the screen is lost. Nothing is recorded for a window that already has them.
+pub fn rs_save_hints(conn: &impl Connection, atoms: &Atoms, window: Window)
+-> Result<(), ReplyError> {
+    let saved = rs_get_property_any(conn, atoms._TABBED_RS_ORIGINAL_HINTS, window)?;
+    if saved.type_ != u32::from(AtomEnum::NONE) {
+        return Ok(());
+    }
+
+    if let Some(hints) = rs_get_size_hints(conn, AtomEnum::WM_NORMAL_HINTS.into(), window)? {
+        hints.set(conn, window, atoms._TABBED_RS_ORIGINAL_HINTS)?;
+    }
+    Ok(())
+}
+
+/// The WM_NORMAL_HINTS a window had before [`rs_restore_window`] marked its position and size as
+/// user specified, to be put back once the window manager has placed the window.
+#[derive(Debug, Clone, Copy)]
+pub struct OriginalHints {
+    pub window: Window,
+    hints: Option<WmSizeHints>,
+}
+
+impl OriginalHints {
+    pub fn restore(&self, conn: &impl Connection) -> Result<(), ConnectionError> {
+        match self.hints {
+            Some(hints) => hints.set_normal_hints(conn, self.window)?,
+            None => conn.delete_property(self.window, AtomEnum::WM_NORMAL_HINTS.into())?,
+        };
+        Ok(())
+    }
+}
+
+/// Reparent a tab of `container` to the root window and map it, where it was before it was
+/// adopted or else at the position of the container.
+///
+/// The position and size are marked as user specified in WM_NORMAL_HINTS so that floating window
+/// managers place the window there instead of at the origin. The caller should put the returned
+/// original hints back once the window is mapped; `StructureNotify` is selected on the window so
+/// its `MapNotify` can be seen.
+pub fn rs_restore_window(conn: &impl Connection, atoms: &Atoms, window: Window, container: Window)
+-> Result<OriginalHints, ReplyError> {
+    let root = conn.get_geometry(container)?.reply()?.root;
+
+    let saved = rs_get_window_property32(conn, atoms._TABBED_RS_ORIGINAL_GEOMETRY, window)?;
+    let (x, y, width, height) = match saved[..] {
+        [x, y, width, height] => (x as i32, y as i32, width, height),
+        _ => {
+            let origin = conn.translate_coordinates(container, root, 0, 0)?.reply()?;
+            let geometry = conn.get_geometry(window)?.reply()?;
+            (origin.dst_x.into(), origin.dst_y.into(), geometry.width.into(), geometry.height.into())
+        },
+    };
+
+    let original = match rs_get_size_hints(conn, atoms._TABBED_RS_ORIGINAL_HINTS, window)? {
+        Some(hints) => Some(hints),
+        None => rs_get_size_hints(conn, AtomEnum::WM_NORMAL_HINTS.into(), window)?,
+    };
+    let mut hints = original.unwrap_or_default();
+    hints.position = Some((WmSizeHintsSpecification::UserSpecified, x, y));
+    hints.size = Some((WmSizeHintsSpecification::UserSpecified, width as i32, height as i32));
+    hints.set_normal_hints(conn, window)?;
+
+    let mask = ChangeWindowAttributesAux::new().event_mask(EventMask::STRUCTURE_NOTIFY);
+    conn.change_window_attributes(window, &mask)?;
+
+    conn.reparent_window(window, root, x as i16, y as i16)?.check()?;
+    conn.configure_window(window, &ConfigureWindowAux::new().width(width).height(height))?;
+    conn.map_window(window)?;
+
+    conn.delete_property(window, atoms._TABBED_RS_ORIGINAL_GEOMETRY)?;
+    conn.delete_property(window, atoms._TABBED_RS_ORIGINAL_HINTS)?;
+    Ok(OriginalHints { window, hints: original })
+}
+
+fn rs_get_size_hints(conn: &impl Connection, property: Atom, window: Window)
+-> Result<Option<WmSizeHints>, ReplyError> {
+    let reply = conn.get_property(false, window, property, AtomEnum::WM_SIZE_HINTS, 0, u32::MAX)?
+        .reply()?;
+    Ok(WmSizeHints::from_reply(&reply).ok())
+}
+
+
+/// Send an action to a tabbed-rs container as a `_TABBED_RS_COMMAND` client message.
+///
+/// The message holds `[code, argument, target]`, where the target is the window of the tab the
+/// action applies to, or 0 for the focused tab. Modes are passed by the atom of their name.
+pub fn rs_send_command(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    container: Window,
+    action: &Action,
+    target: Option<Window>,
+) -> Result<(), ReplyError> {
+    let (code, arg) = encode_command(action, |name| {
+        Ok(conn.intern_atom(false, name.as_bytes())?.reply()?.atom)
+    })?;
+
+    let event = ClientMessageEvent::new(
+        32,
+        container,
+        atoms._TABBED_RS_COMMAND,
+        [code, arg, target.unwrap_or(0), 0, 0],
+    );
+    conn.send_event(false, container, EventMask::NO_EVENT, event)?.check()
+}
+
+/// Decode a `_TABBED_RS_COMMAND` client message sent by [`rs_send_command`].
+///
+/// Any client can send these, so unknown codes are ignored and a mode atom that doesn't exist is
+/// an error for the caller to report rather than to exit over.
+pub fn rs_receive_command(conn: &impl Connection, atoms: &Atoms, event: &ClientMessageEvent)
+-> Result<Option<(Action, Option<Window>)>, ReplyError> {
+    if event.format != 32 || event.type_ != atoms._TABBED_RS_COMMAND {
+        return Ok(None);
+    }
+
+    let [code, arg, target, ..] = event.data.as_data32();
+    let action = decode_command(code, arg, |atom| {
+        let name = conn.get_atom_name(atom)?.reply()?.name;
+        Ok(String::from_utf8_lossy(&name).into_owned())
+    })?;
+
+    Ok(action.map(|action| (action, Some(target).filter(|&w| w != 0))))
+}
+
+/// The code and argument of an action in a `_TABBED_RS_COMMAND` message, with `mode_atom`
+/// looking up the atom for a mode name.
+fn encode_command(action: &Action, mode_atom: impl FnOnce(&str) -> Result<Atom, ReplyError>)
+-> Result<(u32, u32), ReplyError> {
+    Ok(match action {
+        Action::FocusUp => (1, 0),
+        Action::FocusDown => (2, 0),
+        Action::ShiftUp => (3, 0),
+        Action::ShiftDown => (4, 0),
+        Action::Focus(index) => (5, *index as u32),
+        Action::Select => (6, 0),
+        Action::MoveFirst => (7, 0),
+        Action::MoveLast => (8, 0),
+        Action::Close => (9, 0),
+        Action::DetachFocused => (10, 0),
+        Action::DetachAll => (11, 0),
+        Action::ToggleAutoAttach => (12, 0),
+        Action::EnterMode(name) => (13, mode_atom(name)?),
+        Action::ExitMode => (14, 0),
+    })
+}
+
+/// The inverse of [`encode_command`], with `mode_name` looking up the name of a mode atom.
+fn decode_command(code: u32, arg: u32, mode_name: impl FnOnce(Atom) -> Result<String, ReplyError>)
+-> Result<Option<Action>, ReplyError> {
+    Ok(Some(match code {
+        1 => Action::FocusUp,
+        2 => Action::FocusDown,
+        3 => Action::ShiftUp,
+        4 => Action::ShiftDown,
+        5 => Action::Focus(arg as usize),
+        6 => Action::Select,
+        7 => Action::MoveFirst,
+        8 => Action::MoveLast,
+        9 => Action::Close,
+        10 => Action::DetachFocused,
+        11 => Action::DetachAll,
+        12 => Action::ToggleAutoAttach,
+        13 => Action::EnterMode(mode_name(arg)?),
+        14 => Action::ExitMode,
+        _ => return Ok(None),
+    }))
+}
 
 
 
@@ -188,3 +454,70 @@ impl From<Visualtype> for xcb_visualtype_t {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every action, with a match that stops compiling when a variant is added without being
+    /// listed here.
+    fn all_actions() -> Vec<Action> {
+        let actions = vec![
+            Action::FocusUp,
+            Action::FocusDown,
+            Action::ShiftUp,
+            Action::ShiftDown,
+            Action::Focus(3),
+            Action::Select,
+            Action::MoveFirst,
+            Action::MoveLast,
+            Action::Close,
+            Action::DetachFocused,
+            Action::DetachAll,
+            Action::ToggleAutoAttach,
+            Action::EnterMode("resize".to_owned()),
+            Action::ExitMode,
+        ];
+        for action in &actions {
+            match action {
+                Action::FocusUp | Action::FocusDown | Action::ShiftUp | Action::ShiftDown
+                | Action::Focus(_) | Action::Select | Action::MoveFirst | Action::MoveLast
+                | Action::Close | Action::DetachFocused | Action::DetachAll
+                | Action::ToggleAutoAttach | Action::EnterMode(_) | Action::ExitMode => {},
+            }
+        }
+        actions
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        const RESIZE: Atom = 400;
+
+        let mut codes = vec![];
+        for action in all_actions() {
+            let (code, arg) = encode_command(&action, |name| {
+                assert_eq!(name, "resize");
+                Ok(RESIZE)
+            }).unwrap();
+            let decoded = decode_command(code, arg, |atom| {
+                assert_eq!(atom, RESIZE);
+                Ok("resize".to_owned())
+            }).unwrap();
+
+            assert_eq!(decoded, Some(action));
+            codes.push(code);
+        }
+
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), all_actions().len(), "codes must be distinct");
+    }
+
+    #[test]
+    fn unknown_commands_are_ignored() {
+        let no_modes = |_| -> Result<String, ReplyError> { unreachable!() };
+        assert_eq!(decode_command(0, 0, no_modes).unwrap(), None);
+        assert_eq!(decode_command(99, 0, no_modes).unwrap(), None);
+    }
+}